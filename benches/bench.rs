@@ -60,6 +60,17 @@ fn encode_medium_cbor(b: &mut test::Bencher) {
     });
 }
 
+#[bench]
+fn encode_large_strings_cbor(b: &mut test::Bencher) {
+    let data = repeat("hello, world ").take(10_000).collect::<String>();
+    let items = repeat(&data).take(100).collect::<Vec<_>>();
+
+    b.bytes = cbor_encode(&items).len() as u64;
+    b.iter(|| {
+        cbor_encode(&items);
+    });
+}
+
 #[bench]
 fn encode_medium_json(b: &mut test::Bencher) {
     let data = ("hello, world".to_string(),
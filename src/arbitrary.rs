@@ -0,0 +1,94 @@
+use std::collections::HashMap;
+
+use quickcheck::{Arbitrary, Gen};
+
+use half;
+use {Cbor, CborBytes, CborFloat, CborSigned, CborTag, CborUnsigned};
+
+/// The maximum depth of arrays, maps and tags in a generated value.
+const MAX_DEPTH: usize = 3;
+
+/// Generates values of every variant except `Cbor::Break`, which isn't a
+/// data item on its own.
+///
+/// Every generated value decodes back to itself after being written with
+/// `Encoder::encode_cbor`. In particular, no float is NaN (since NaN isn't
+/// equal to itself), and every negative integer has the width that the
+/// decoder would give it.
+impl Arbitrary for Cbor {
+    fn arbitrary<G: Gen>(g: &mut G) -> Cbor {
+        arbitrary_at(g, MAX_DEPTH)
+    }
+
+    fn shrink(&self) -> Box<Iterator<Item=Cbor>> {
+        match *self {
+            Cbor::Array(ref vals) => Box::new(vals.clone().into_iter()),
+            Cbor::Map(ref map) => {
+                let vals: Vec<Cbor> = map.values().cloned().collect();
+                Box::new(vals.into_iter())
+            }
+            Cbor::Tag(ref tag) => {
+                Box::new(Some((*tag.data).clone()).into_iter())
+            }
+            _ => Box::new(None.into_iter()),
+        }
+    }
+}
+
+fn arbitrary_at<G: Gen>(g: &mut G, depth: usize) -> Cbor {
+    let variants = if depth == 0 { 8 } else { 11 };
+    match g.gen_range(0, variants) {
+        0 => Cbor::Undefined,
+        1 => Cbor::Null,
+        2 => Cbor::Bool(g.gen()),
+        3 => Cbor::Unsigned(match g.gen_range(0, 4) {
+            0 => CborUnsigned::UInt8(g.gen()),
+            1 => CborUnsigned::UInt16(g.gen()),
+            2 => CborUnsigned::UInt32(g.gen()),
+            _ => CborUnsigned::UInt64(g.gen()),
+        }),
+        4 => Cbor::Signed(match g.gen_range(0, 4) {
+            0 => CborSigned::Int8(g.gen_range(::std::i8::MIN, 0)),
+            1 => CborSigned::Int16(
+                g.gen_range(::std::i16::MIN, ::std::i8::MIN as i16)),
+            2 => CborSigned::Int32(
+                g.gen_range(::std::i32::MIN, ::std::i16::MIN as i32)),
+            _ => CborSigned::Int64(
+                g.gen_range(::std::i64::MIN, ::std::i32::MIN as i64)),
+        }),
+        5 => Cbor::Float(arbitrary_float(g)),
+        6 => Cbor::Bytes(CborBytes(Arbitrary::arbitrary(g))),
+        7 => Cbor::Unicode(Arbitrary::arbitrary(g)),
+        8 => {
+            let len = g.gen_range(0, 4);
+            Cbor::Array((0..len).map(|_| arbitrary_at(g, depth - 1))
+                                .collect())
+        }
+        9 => {
+            let len = g.gen_range(0, 4);
+            let mut map = HashMap::new();
+            for _ in 0..len {
+                let k = Arbitrary::arbitrary(g);
+                map.insert(k, arbitrary_at(g, depth - 1));
+            }
+            Cbor::Map(map)
+        }
+        _ => Cbor::Tag(CborTag {
+            tag: g.gen(),
+            data: Box::new(arbitrary_at(g, depth - 1)),
+        }),
+    }
+}
+
+fn arbitrary_float<G: Gen>(g: &mut G) -> CborFloat {
+    loop {
+        let f = match g.gen_range(0, 3) {
+            0 => CborFloat::Float16(half::to_f32(g.gen())),
+            1 => CborFloat::Float32(Arbitrary::arbitrary(g)),
+            _ => CborFloat::Float64(Arbitrary::arbitrary(g)),
+        };
+        if !f.to_f64().unwrap().is_nan() {
+            return f;
+        }
+    }
+}
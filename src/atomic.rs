@@ -0,0 +1,61 @@
+use std::fmt;
+use std::sync::atomic::{
+    AtomicI16, AtomicI32, AtomicI64, AtomicI8, AtomicIsize, AtomicU16,
+    AtomicU32, AtomicU64, AtomicU8, AtomicUsize, Ordering,
+};
+
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+macro_rules! atomic_wrappers {
+    ($($name:ident($atomic:ident, $int:ident)),*) => {$(
+        #[doc = concat!("An `", stringify!($atomic), "` that can be encoded \
+                         and decoded as a plain integer.")]
+        ///
+        /// The value is loaded with `Ordering::SeqCst` when encoding.
+        /// Decoding constructs a new atomic holding the decoded integer.
+        #[derive(Default)]
+        pub struct $name(pub $atomic);
+
+        impl $name {
+            /// Create a new wrapper holding `v`.
+            pub fn new(v: $int) -> $name {
+                $name($atomic::new(v))
+            }
+        }
+
+        impl From<$atomic> for $name {
+            fn from(v: $atomic) -> $name { $name(v) }
+        }
+
+        impl fmt::Debug for $name {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Debug::fmt(&self.0, f)
+            }
+        }
+
+        impl Encodable for $name {
+            fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+                self.0.load(Ordering::SeqCst).encode(e)
+            }
+        }
+
+        impl Decodable for $name {
+            fn decode<D: Decoder>(d: &mut D) -> Result<$name, D::Error> {
+                Ok($name::new(try!(Decodable::decode(d))))
+            }
+        }
+    )*}
+}
+
+atomic_wrappers! {
+    CborAtomicU8(AtomicU8, u8),
+    CborAtomicU16(AtomicU16, u16),
+    CborAtomicU32(AtomicU32, u32),
+    CborAtomicU64(AtomicU64, u64),
+    CborAtomicUsize(AtomicUsize, usize),
+    CborAtomicI8(AtomicI8, i8),
+    CborAtomicI16(AtomicI16, i16),
+    CborAtomicI32(AtomicI32, i32),
+    CborAtomicI64(AtomicI64, i64),
+    CborAtomicIsize(AtomicIsize, isize)
+}
@@ -0,0 +1,58 @@
+use std::collections::BinaryHeap;
+
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+/// A `std::collections::BinaryHeap` that can be encoded and decoded.
+///
+/// The heap is encoded as an array of its elements in the heap's own
+/// iteration order, which is unspecified (in particular, the elements
+/// aren't sorted). Decoding collects the elements into a fresh heap, so
+/// only the elements themselves, and hence the heap's behaviour, survive a
+/// round trip. The order of the encoded array may differ.
+///
+/// # Example
+///
+/// ```rust
+/// use cbor::{CborBinaryHeap, Decoder, Encoder};
+///
+/// let heap = CborBinaryHeap(vec![3, 1, 2].into_iter().collect());
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[&heap]).unwrap();
+///
+/// let mut dec = Decoder::from_bytes(enc.as_bytes());
+/// let mut decoded: CborBinaryHeap<u8> =
+///     dec.decode().next().unwrap().unwrap();
+/// assert_eq!(decoded.0.pop(), Some(3));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct CborBinaryHeap<T: Ord>(pub BinaryHeap<T>);
+
+impl<T: Ord> From<BinaryHeap<T>> for CborBinaryHeap<T> {
+    fn from(v: BinaryHeap<T>) -> CborBinaryHeap<T> {
+        CborBinaryHeap(v)
+    }
+}
+
+impl<T: Ord + Encodable> Encodable for CborBinaryHeap<T> {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        e.emit_seq(self.0.len(), |e| {
+            for (i, v) in self.0.iter().enumerate() {
+                try!(e.emit_seq_elt(i, |e| v.encode(e)));
+            }
+            Ok(())
+        })
+    }
+}
+
+impl<T: Ord + Decodable> Decodable for CborBinaryHeap<T> {
+    fn decode<D: Decoder>(d: &mut D) -> Result<CborBinaryHeap<T>, D::Error> {
+        d.read_seq(|d, len| {
+            let mut heap = BinaryHeap::with_capacity(len);
+            for i in 0..len {
+                heap.push(try!(d.read_seq_elt(i, Decodable::decode)));
+            }
+            Ok(CborBinaryHeap(heap))
+        })
+    }
+}
@@ -0,0 +1,42 @@
+use {CborResult, Decoder, Encoder, split_sequence};
+
+/// Returns whether two encodings of a sequence of CBOR data items represent
+/// the same values.
+///
+/// Both sequences are decoded, canonicalized (see `Cbor::canonicalize`) and
+/// compared data item by data item, so differences in integer and float
+/// widths, definite or indefinite lengths and the order of map entries are
+/// ignored. Tags must have the same tag numbers. Unlike comparing `Cbor`
+/// values with `==`, NaN is equal to NaN.
+///
+/// An error is returned if either sequence isn't well-formed or can't be
+/// decoded.
+///
+/// # Example
+///
+/// ```rust
+/// use cbor::bytes_eq_canonical;
+///
+/// // [1, 1.5] and [_ 1, 1.5] with a wider integer and float.
+/// let a = [0x82, 0x01, 0xf9, 0x3e, 0x00];
+/// let b = [0x9f, 0x18, 0x01, 0xfa, 0x3f, 0xc0, 0x00, 0x00, 0xff];
+/// assert!(bytes_eq_canonical(&a, &b).unwrap());
+/// assert!(!bytes_eq_canonical(&a, &[0x82, 0x01, 0x02]).unwrap());
+/// ```
+pub fn bytes_eq_canonical(a: &[u8], b: &[u8]) -> CborResult<bool> {
+    Ok(try!(canonical_bytes(a)) == try!(canonical_bytes(b)))
+}
+
+/// Re-encodes every data item in `bytes` in canonical form.
+fn canonical_bytes(bytes: &[u8]) -> CborResult<Vec<u8>> {
+    // The decoder stops quietly at a truncated data item, so check that
+    // every item is complete first.
+    try!(split_sequence(bytes));
+    let mut enc = Encoder::from_memory();
+    for v in Decoder::from_bytes(bytes).items() {
+        let mut v = try!(v);
+        v.canonicalize();
+        try!(enc.encode_cbor(&v));
+    }
+    Ok(enc.into_bytes())
+}
@@ -0,0 +1,67 @@
+use std::ops::ControlFlow;
+
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+/// A `std::ops::ControlFlow` that can be encoded and decoded.
+///
+/// This uses the same representation as a derived enum would, where
+/// `Continue` is variant 0 and `Break` is variant 1, e.g.,
+/// `{"variant": "Break", "fields": [5]}`.
+///
+/// # Example
+///
+/// ```rust
+/// use std::ops::ControlFlow;
+/// use cbor::{CborControlFlow, Decoder, Encoder};
+///
+/// let flow: CborControlFlow<u8> = CborControlFlow(ControlFlow::Break(5));
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[&flow]).unwrap();
+///
+/// let mut dec = Decoder::from_bytes(enc.as_bytes());
+/// let decoded: CborControlFlow<u8> = dec.decode().next().unwrap().unwrap();
+/// assert_eq!(decoded, flow);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct CborControlFlow<B, C = ()>(pub ControlFlow<B, C>);
+
+impl<B, C> From<ControlFlow<B, C>> for CborControlFlow<B, C> {
+    fn from(v: ControlFlow<B, C>) -> CborControlFlow<B, C> {
+        CborControlFlow(v)
+    }
+}
+
+impl<B: Encodable, C: Encodable> Encodable for CborControlFlow<B, C> {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        e.emit_enum("ControlFlow", |e| match self.0 {
+            ControlFlow::Continue(ref v) => {
+                e.emit_enum_variant("Continue", 0, 1, |e| {
+                    e.emit_enum_variant_arg(0, |e| v.encode(e))
+                })
+            }
+            ControlFlow::Break(ref v) => {
+                e.emit_enum_variant("Break", 1, 1, |e| {
+                    e.emit_enum_variant_arg(0, |e| v.encode(e))
+                })
+            }
+        })
+    }
+}
+
+impl<B: Decodable, C: Decodable> Decodable for CborControlFlow<B, C> {
+    fn decode<D: Decoder>(d: &mut D)
+                         -> Result<CborControlFlow<B, C>, D::Error> {
+        d.read_enum("ControlFlow", |d| {
+            d.read_enum_variant(&["Continue", "Break"], |d, i| {
+                Ok(CborControlFlow(match i {
+                    0 => ControlFlow::Continue(try!(d.read_enum_variant_arg(
+                        0, Decodable::decode))),
+                    1 => ControlFlow::Break(try!(d.read_enum_variant_arg(
+                        0, Decodable::decode))),
+                    _ => return Err(d.error("Unknown ControlFlow variant.")),
+                }))
+            })
+        })
+    }
+}
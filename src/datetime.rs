@@ -0,0 +1,94 @@
+//! Checking of standard date/time strings (tag 0).
+
+macro_rules! try_opt {
+    ($e:expr) => (match $e { Some(v) => v, None => return None });
+}
+
+/// Checks that `s` is a standard date/time string and returns it in the
+/// strict form.
+///
+/// The strict form is RFC 3339 as refined by RFC 4287 (section 3.3), which is
+/// what RFC 8949 requires for tag 0, e.g., `2013-03-21T20:04:00.5+01:00`. In
+/// particular, `T` and `Z` must be upper case. When `lenient` is set, a lower
+/// case `t` or `z`, a space instead of `T`, an offset without a colon (e.g.,
+/// `+0100`) and a missing offset (which is taken as UTC) are accepted too.
+///
+/// Returns `None` if `s` isn't valid.
+pub fn normalize(s: &str, lenient: bool) -> Option<String> {
+    let b = s.as_bytes();
+    if b.len() < 19 {
+        return None;
+    }
+    let (year, month, day) = (
+        try_opt!(digits(&b[0..4])),
+        try_opt!(digits(&b[5..7])),
+        try_opt!(digits(&b[8..10])),
+    );
+    let (hour, minute, second) = (
+        try_opt!(digits(&b[11..13])),
+        try_opt!(digits(&b[14..16])),
+        try_opt!(digits(&b[17..19])),
+    );
+    if b[4] != b'-' || b[7] != b'-' || b[13] != b':' || b[16] != b':' {
+        return None;
+    }
+    match b[10] {
+        b'T' => {}
+        b't' | b' ' if lenient => {}
+        _ => return None,
+    }
+    if month < 1 || month > 12 || day < 1 || day > days_in(year, month)
+       || hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+
+    let mut i = 19;
+    if b.get(i) == Some(&b'.') {
+        i += 1;
+        let start = i;
+        while b.get(i).map_or(false, |c| c.is_ascii_digit()) {
+            i += 1;
+        }
+        if i == start {
+            return None;
+        }
+    }
+    let offset = match &b[i..] {
+        b"Z" => "Z".to_owned(),
+        b"z" | b"" if lenient => "Z".to_owned(),
+        &[sign, h1, h2, b':', m1, m2]
+        | &[sign, h1, h2, m1, m2] if sign == b'+' || sign == b'-' => {
+            if b.len() - i == 5 && !lenient {
+                return None;
+            }
+            let hours = try_opt!(digits(&[h1, h2]));
+            let minutes = try_opt!(digits(&[m1, m2]));
+            if hours > 23 || minutes > 59 {
+                return None;
+            }
+            format!("{}{:02}:{:02}", sign as char, hours, minutes)
+        }
+        _ => return None,
+    };
+    Some(format!("{}T{}{}", &s[0..10], &s[11..i], offset))
+}
+
+fn digits(b: &[u8]) -> Option<u32> {
+    let mut n = 0;
+    for &c in b {
+        if !c.is_ascii_digit() {
+            return None;
+        }
+        n = n * 10 + (c - b'0') as u32;
+    }
+    Some(n)
+}
+
+fn days_in(year: u32, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
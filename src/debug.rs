@@ -0,0 +1,111 @@
+use {Cbor, CborResult, Decoder, Event, split_sequence};
+
+/// Returns a side by side dump of the hex bytes and the meaning of every
+/// header in a sequence of CBOR data items, for debugging.
+///
+/// Each line has the bytes of one header, indented by its nesting depth,
+/// followed by a description of it. Arrays, maps and tags are described by
+/// their length or number (`*` for an indefinite length), and the contents
+/// come on the lines after them. Every other data item is written whole in
+/// diagnostic notation (see `Cbor`'s `Display` impl), including the bytes
+/// of its content.
+///
+/// An error is returned if the bytes aren't a well-formed sequence of data
+/// items.
+///
+/// # Example
+///
+/// ```rust
+/// // [1, {"a": h'ff'}]
+/// let dump = cbor::debug_dump(&[0x82, 0x01, 0xa1, 0x61, 0x61, 0x41, 0xff])
+///                .unwrap();
+/// assert_eq!(dump, "\
+/// 82           # array(2)
+///    01        # 1
+///    a1        # map(1)
+///       61 61  # \"a\"
+///       41 ff  # h'ff'
+/// ");
+/// ```
+pub fn debug_dump(bytes: &[u8]) -> CborResult<String> {
+    try!(split_sequence(bytes));
+    let mut dec = Decoder::from_bytes(bytes);
+    let mut lines = vec![];
+    // The number of data items left in each enclosing array, map or tag, or
+    // `None` for an indefinite length.
+    let mut open: Vec<Option<usize>> = vec![];
+    let mut start = 0;
+    while let Some(ev) = dec.events().next() {
+        let ev = try!(ev);
+        let end = dec.bytes_read();
+        if ev == Event::Break {
+            open.pop();
+        }
+        let hex: Vec<String> =
+            bytes[start..end].iter().map(|b| format!("{:02x}", b)).collect();
+        let indent = "   ".repeat(open.len());
+        lines.push((indent + &hex.join(" "), describe(&ev)));
+        start = end;
+        match ev {
+            Event::ArrayStart(len) if len != Some(0) => {
+                open.push(len);
+                continue;
+            }
+            Event::MapStart(len) if len != Some(0) => {
+                open.push(len.map(|len| len.saturating_mul(2)));
+                continue;
+            }
+            Event::Tag(_) => {
+                open.push(Some(1));
+                continue;
+            }
+            _ => {}
+        }
+        // A data item was completed. Close every array, map and tag that it
+        // filled up.
+        loop {
+            match open.last_mut() {
+                Some(&mut Some(ref mut left)) => {
+                    *left -= 1;
+                    if *left > 0 {
+                        break;
+                    }
+                }
+                _ => break,
+            }
+            open.pop();
+        }
+    }
+    let width = lines.iter().map(|line| line.0.len()).max().unwrap_or(0);
+    let mut dump = String::new();
+    for (hex, desc) in lines {
+        dump.push_str(&format!("{:2$}  # {}\n", hex, desc, width));
+    }
+    Ok(dump)
+}
+
+fn describe(ev: &Event) -> String {
+    let v = match *ev {
+        Event::ArrayStart(len) => return container("array", len),
+        Event::MapStart(len) => return container("map", len),
+        Event::Tag(tag) => return format!("tag({})", tag),
+        Event::Break => return "break".to_owned(),
+        Event::Unsigned(v) => Cbor::Unsigned(v),
+        Event::Signed(v) => Cbor::Signed(v),
+        Event::Float(v) => Cbor::Float(v),
+        Event::Bool(v) => Cbor::Bool(v),
+        Event::Null => Cbor::Null,
+        Event::Undefined => Cbor::Undefined,
+        Event::Simple(v) => Cbor::Simple(v),
+        Event::Bytes(ref v) => Cbor::Bytes(v.clone()),
+        Event::Unicode(ref v) => Cbor::Unicode(v.clone()),
+    };
+    v.to_string()
+}
+
+fn container(name: &str, len: Option<usize>) -> String {
+    match len {
+        Some(len) => format!("{}({})", name, len),
+        None => format!("{}(*)", name),
+    }
+}
@@ -2,26 +2,332 @@ use std::cmp;
 use std::convert::Into;
 use std::collections::hash_map::HashMap;
 use std::io::{self, Read};
-use std::mem::transmute;
 
+#[cfg(feature = "bytes")]
+use bytes::Bytes;
 use byteorder::{ReadBytesExt, BigEndian};
 use rustc_serialize::Decodable;
 
+use datetime;
+use half;
 use rustc_decoder::CborDecoder;
 use {
-    Cbor, CborUnsigned, CborSigned, CborFloat, CborBytes, CborTag, Type,
-    CborResult, CborError, ReadError,
+    Cbor, CborUnsigned, CborSigned, CborFloat, CborBytes, CborTag, Event,
+    SimpleValues, Type, CborResult, CborError, ReadError,
 };
 
 /// Read CBOR data items into Rust values from the underlying reader `R`.
 pub struct Decoder<R> {
     rdr: CborReader<R>,
+    forbid_indefinite: bool,
+    collapse_repeated_tags: bool,
+    max_string_len: usize,
+    reject_nonfinite_floats: bool,
+    reject_duplicate_keys: bool,
+    reject_non_canonical: bool,
+    drop_undefined_map_values: bool,
+    simple_values: SimpleValues,
+    accept_array_as_map: bool,
+    accept_field0_maps: bool,
+    datetime_mode: DateTimeMode,
+    max_total_tags: usize,
+    total_tags: usize,
+    max_map_entries: usize,
+    strict_floats: bool,
+    skip_leading_junk: bool,
+    leading_junk: Vec<u8>,
+    max_items: usize,
+    // The number of data items read so far in the current top-level data
+    // item.
+    items_read: usize,
+}
+
+/// How standard date/time strings (tag 0) are checked, for
+/// `Decoder::set_datetime_mode`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum DateTimeMode {
+    /// The tagged data item isn't checked at all.
+    Unchecked,
+    /// The tagged data item must be a date/time string in the exact format
+    /// that RFC 8949 requires: RFC 3339, as refined by section 3.3 of RFC
+    /// 4287. For example, `2013-03-21T20:04:00Z` or
+    /// `2013-03-21T21:04:00.25+01:00`.
+    Strict,
+    /// Like `Strict`, but common deviations are accepted too: a lower case
+    /// `t` or `z`, a space instead of `T`, an offset without a colon (e.g.,
+    /// `+0100`) and a missing offset, which is taken to be UTC. The string is
+    /// rewritten in the strict format, so that consumers of the tag only
+    /// ever see one format.
+    Lenient,
 }
 
 impl<R: io::Read> Decoder<R> {
     /// Create a new CBOR decoder from the underlying reader.
     pub fn from_reader(rdr: R) -> Decoder<io::BufReader<R>> {
-        Decoder { rdr: CborReader::new(io::BufReader::new(rdr)) }
+        Decoder::new(CborReader::new(io::BufReader::new(rdr)))
+    }
+
+    fn new(rdr: CborReader<R>) -> Decoder<R> {
+        Decoder {
+            rdr: rdr,
+            forbid_indefinite: false,
+            collapse_repeated_tags: false,
+            max_string_len: ::std::usize::MAX,
+            reject_nonfinite_floats: false,
+            reject_duplicate_keys: false,
+            reject_non_canonical: false,
+            drop_undefined_map_values: false,
+            simple_values: SimpleValues::default(),
+            accept_array_as_map: false,
+            accept_field0_maps: false,
+            datetime_mode: DateTimeMode::Unchecked,
+            max_total_tags: ::std::usize::MAX,
+            total_tags: 0,
+            max_map_entries: ::std::usize::MAX,
+            strict_floats: false,
+            skip_leading_junk: false,
+            leading_junk: b" \t\r\n".to_vec(),
+            max_items: ::std::usize::MAX,
+            items_read: 0,
+        }
+    }
+
+    /// When enabled, decoding any indefinite length byte string, Unicode
+    /// string, array or map returns an error.
+    ///
+    /// This is disabled by default, which means indefinite length data items
+    /// are decoded just like their definite length counterparts.
+    pub fn set_forbid_indefinite(&mut self, yes: bool) {
+        self.forbid_indefinite = yes;
+    }
+
+    /// When enabled, a tag whose data item is the same tag is collapsed into
+    /// a single tag, e.g., `tag 1 (tag 1 (x))` is decoded as `tag 1 (x)`.
+    ///
+    /// This is a lenient mode for producers that wrap a value in the same
+    /// tag more than once by mistake. It is disabled by default.
+    pub fn set_collapse_repeated_tags(&mut self, yes: bool) {
+        self.collapse_repeated_tags = yes;
+    }
+
+    /// Set the maximum length, in bytes, of any single byte string or
+    /// Unicode string.
+    ///
+    /// Decoding a longer string returns an error, which is reported as soon
+    /// as the string's header has been read. For indefinite length strings,
+    /// the total length of all chunks is limited. There is no limit by
+    /// default.
+    pub fn set_max_string_len(&mut self, len: usize) {
+        self.max_string_len = len;
+    }
+
+    /// Set the maximum number of tags that may be decoded.
+    ///
+    /// Every tag read counts towards the limit, however deeply it is nested,
+    /// and the count is kept across everything read by this decoder (not
+    /// reset for each data item). Decoding the tag that exceeds the limit
+    /// returns an error. This guards against payloads that are mostly tags.
+    /// There is no limit by default.
+    pub fn set_max_total_tags(&mut self, n: usize) {
+        self.max_total_tags = n;
+    }
+
+    /// Set the maximum number of entries in any single map.
+    ///
+    /// Decoding a map with more entries returns an error. For definite
+    /// length maps, this is reported as soon as the map's header has been
+    /// read. For indefinite length maps, it is reported when the first
+    /// entry past the limit is read. (Maps read one entry at a time with
+    /// `decode_map_entries` aren't limited.) There is no limit by default.
+    pub fn set_max_map_entries(&mut self, n: usize) {
+        self.max_map_entries = n;
+    }
+
+    /// Set the maximum number of data items in any single top-level data
+    /// item.
+    ///
+    /// Every data item counts towards the limit, including the top-level
+    /// data item itself, every array, map and tag along with everything in
+    /// them, and the break that ends an indefinite length array or map. (The
+    /// chunks of an indefinite length string are not counted separately.)
+    /// Decoding the data item that exceeds the limit returns an error. This
+    /// bounds the work done for each top-level data item, no matter how
+    /// small its data items are.
+    ///
+    /// The whole of an array read with `decode_vec_partial`, a map read
+    /// with `decode_map_entries` or a record read with `decode_tuple_struct`
+    /// counts as one top-level data item (including its header), as does
+    /// everything read through one `events` iterator. There is no limit by
+    /// default.
+    pub fn set_max_items(&mut self, n: usize) {
+        self.max_items = n;
+    }
+
+    /// When enabled, decoding a floating point number that is NaN or
+    /// infinite returns an error.
+    ///
+    /// This is disabled by default.
+    pub fn set_reject_nonfinite_floats(&mut self, yes: bool) {
+        self.reject_nonfinite_floats = yes;
+    }
+
+    /// When enabled, decoding a map with the same key more than once returns
+    /// an error.
+    ///
+    /// This is disabled by default, which means the last value for a key
+    /// wins.
+    pub fn set_reject_duplicate_keys(&mut self, yes: bool) {
+        self.reject_duplicate_keys = yes;
+    }
+
+    /// When enabled, decoding data that isn't in canonical form returns an
+    /// error.
+    ///
+    /// Data is canonical when every integer, length and tag number uses the
+    /// shortest possible encoding, every float uses the shortest width that
+    /// represents it exactly, no length is indefinite and the keys of every
+    /// map are in canonical order (shorter keys first, then byte-wise). This
+    /// is the order written by `Encoder::encode_cbor`, and the same as the
+    /// core deterministic encoding of RFC 8949 for string keys.
+    ///
+    /// This is disabled by default.
+    pub fn set_reject_non_canonical(&mut self, yes: bool) {
+        self.reject_non_canonical = yes;
+    }
+
+    /// When enabled, map entries whose value is `undefined` are left out of
+    /// the decoded map, as if they weren't there at all.
+    ///
+    /// This also applies to the absent value of tag 31 (i.e., `undefined`
+    /// under tag 31), which some schemas use for the same purpose.
+    ///
+    /// This is disabled by default.
+    pub fn set_drop_undefined_map_values(&mut self, yes: bool) {
+        self.drop_undefined_map_values = yes;
+    }
+
+    /// Set the simple values that are decoded as `false`, `true` and
+    /// `null`, in addition to the standard ones.
+    ///
+    /// This is the counterpart of `Encoder::set_simple_value_map`. Other
+    /// unassigned simple values are decoded as `Cbor::Simple`. This is
+    /// `SimpleValues::default()` by default, i.e., only the standard simple
+    /// values are given a meaning.
+    pub fn set_simple_value_map(&mut self, map: SimpleValues) {
+        self.simple_values = map;
+    }
+
+    /// When enabled, a map (e.g., a `HashMap`) can also be decoded from an
+    /// array of `[key, value]` arrays, which some producers write instead.
+    ///
+    /// Each element of the array must be an array of exactly two elements.
+    /// Since the keys are decoded like any other value, they don't have to
+    /// be Unicode strings. This is disabled by default.
+    pub fn set_accept_array_as_map(&mut self, yes: bool) {
+        self.accept_array_as_map = yes;
+    }
+
+    /// When enabled, a tuple struct with one field (a newtype) can also be
+    /// decoded from a map whose only key is `_field0`, which is how older
+    /// versions of this crate encoded newtypes.
+    ///
+    /// This is disabled by default, because the same map is also a valid
+    /// encoding of a newtype around a map (e.g., a `HashMap<String, u64>`),
+    /// which would then be misread.
+    pub fn set_accept_field0_maps(&mut self, yes: bool) {
+        self.accept_field0_maps = yes;
+    }
+
+    /// When enabled, only floating point numbers (of any width) can be
+    /// decoded into an `f32` or `f64`. The exception is an epoch time in
+    /// tag 1, which may always be an integer.
+    ///
+    /// This is disabled by default, which means integers are converted to
+    /// floats too (as they always have been, so existing data that writes
+    /// whole numbers as integers keeps decoding). In both cases, a null can
+    /// be decoded as a `None` of an `Option<f64>`.
+    pub fn set_strict_floats(&mut self, yes: bool) {
+        self.strict_floats = yes;
+    }
+
+    /// When enabled, a UTF-8 byte order mark and any of the leading junk
+    /// bytes (see `set_leading_junk_bytes`) are skipped before the first
+    /// data item.
+    ///
+    /// This is a recovery mode for data that was mangled by a tool that
+    /// treats it as text. Only the bytes at the very start of the input are
+    /// skipped. Note that the default junk bytes (ASCII whitespace) are also
+    /// valid CBOR headers, e.g., a newline is the integer `10`, so an input
+    /// that starts with such a data item is misread. This is disabled by
+    /// default.
+    pub fn set_skip_leading_junk(&mut self, yes: bool) {
+        self.skip_leading_junk = yes;
+    }
+
+    /// Set the bytes that are skipped before the first data item when
+    /// `set_skip_leading_junk` is enabled.
+    ///
+    /// By default, these are the ASCII whitespace bytes space, tab, carriage
+    /// return and newline. A UTF-8 byte order mark is always skipped.
+    pub fn set_leading_junk_bytes(&mut self, bytes: Vec<u8>) {
+        self.leading_junk = bytes;
+    }
+
+    /// Set how standard date/time strings (tag 0) are checked.
+    ///
+    /// When this is `DateTimeMode::Strict` or `DateTimeMode::Lenient`,
+    /// decoding a tag 0 whose data item isn't a valid date/time string
+    /// returns an error. This is `DateTimeMode::Unchecked` by default.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{Cbor, DateTimeMode, Decoder};
+    ///
+    /// // 0("2013-03-21t20:04:00z")
+    /// let mut bytes = vec![0xc0, 0x74];
+    /// bytes.extend_from_slice(b"2013-03-21t20:04:00z");
+    ///
+    /// let mut dec = Decoder::from_bytes(&bytes[..]);
+    /// dec.set_datetime_mode(DateTimeMode::Strict);
+    /// assert!(dec.items().next().unwrap().is_err());
+    ///
+    /// let mut dec = Decoder::from_bytes(&bytes[..]);
+    /// dec.set_datetime_mode(DateTimeMode::Lenient);
+    /// match dec.items().next().unwrap().unwrap() {
+    ///     Cbor::Tag(tag) => {
+    ///         assert_eq!(*tag.data,
+    ///                    Cbor::Unicode("2013-03-21T20:04:00Z".to_owned()));
+    ///     }
+    ///     _ => unreachable!(),
+    /// }
+    /// ```
+    pub fn set_datetime_mode(&mut self, mode: DateTimeMode) {
+        self.datetime_mode = mode;
+    }
+
+    /// Turn on every check needed for strict decoding as recommended by
+    /// RFC 8949.
+    ///
+    /// This enables `set_reject_non_canonical`, `set_reject_duplicate_keys`
+    /// and `set_forbid_indefinite`. Invalid UTF-8, reserved and unassigned
+    /// additional information values, and breaks outside of indefinite
+    /// length data items are always rejected, even without this.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Decoder;
+    ///
+    /// // 1, encoded with two bytes instead of one.
+    /// let mut dec = Decoder::from_bytes(vec![0x18, 0x01]).strict_rfc8949();
+    /// assert!(dec.items().next().unwrap().is_err());
+    /// ```
+    pub fn strict_rfc8949(mut self) -> Decoder<R> {
+        self.set_reject_non_canonical(true);
+        self.set_reject_duplicate_keys(true);
+        self.set_forbid_indefinite(true);
+        self
     }
 }
 
@@ -94,10 +400,323 @@ impl<R: io::Read> Decoder<R> {
         Items { dec: self }
     }
 
+    /// Read a sequence of top-level CBOR data items along with the exact
+    /// bytes that each one was decoded from.
+    ///
+    /// This is like `items`, but the encoded bytes of every item are
+    /// captured as they are read, which is useful for keeping the original
+    /// next to the parsed form.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{Decoder, Encoder};
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// enc.encode(&[1, 1000]).unwrap();
+    ///
+    /// let mut dec = Decoder::from_bytes(enc.as_bytes());
+    /// let raws: Vec<Vec<u8>> = dec.items_with_raw()
+    ///                             .map(|item| item.unwrap().0)
+    ///                             .collect();
+    /// assert_eq!(raws, vec![vec![0x01], vec![0x19, 0x03, 0xe8]]);
+    /// ```
+    pub fn items_with_raw(&mut self) -> RawItems<R> {
+        RawItems { dec: self }
+    }
+
+    /// Decode the entries of a single map one at a time.
+    ///
+    /// The next data item must be a map, which may have a definite or an
+    /// indefinite length. Its entries are decoded into keys of type `K` and
+    /// values of type `V` as the iterator is advanced, so the map is never
+    /// held in memory all at once. Iteration stops after the last entry, or
+    /// after the first error.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use cbor::{Decoder, Encoder};
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("a".to_string(), 1);
+    /// map.insert("b".to_string(), 2);
+    /// let mut enc = Encoder::from_memory();
+    /// enc.encode(&[map]).unwrap();
+    ///
+    /// let mut dec = Decoder::from_bytes(enc.as_bytes());
+    /// let sum = dec.decode_map_entries::<String, i32>()
+    ///              .map(|entry| entry.unwrap().1)
+    ///              .fold(0, |sum, v| sum + v);
+    /// assert_eq!(sum, 3);
+    /// ```
+    pub fn decode_map_entries<K, V>(&mut self) -> MapEntries<R, K, V>
+            where K: Decodable, V: Decodable {
+        self.items_read = 0;
+        MapEntries {
+            dec: self,
+            left: None,
+            done: false,
+            _phantom: ::std::marker::PhantomData,
+        }
+    }
+
+    /// Read a sequence of CBOR events.
+    ///
+    /// This is the lowest level way to read CBOR data. Instead of building a
+    /// `Cbor` value for each data item, every header is reported as it is
+    /// read, which keeps memory use flat no matter how deeply data items are
+    /// nested. In particular, each tag is its own `Event::Tag` event,
+    /// followed by the events of the data item it tags.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{CborUnsigned, Decoder, Event};
+    ///
+    /// // [1, 2(3)]
+    /// let mut dec = Decoder::from_bytes(vec![0x82, 0x01, 0xc2, 0x03]);
+    /// let events = dec.events().collect::<Result<Vec<_>, _>>().unwrap();
+    /// assert_eq!(events, vec![
+    ///     Event::ArrayStart(Some(2)),
+    ///     Event::Unsigned(CborUnsigned::UInt8(1)),
+    ///     Event::Tag(2),
+    ///     Event::Unsigned(CborUnsigned::UInt8(3)),
+    /// ]);
+    /// ```
+    pub fn events(&mut self) -> Events<R> {
+        self.items_read = 0;
+        Events { dec: self }
+    }
+
+    fn read_event(&mut self) -> CborResult<Event> {
+        let first = try!(self.read_first());
+        Ok(match (first & 0b111_00000) >> 5 {
+            4 => {
+                try!(self.count_item());
+                Event::ArrayStart(try!(self.read_len(first)))
+            }
+            5 => {
+                try!(self.count_item());
+                Event::MapStart(try!(self.read_len(first)))
+            }
+            6 => {
+                try!(self.count_item());
+                try!(self.count_tag());
+                let tag = try!(self.read_uint(first));
+                Event::Tag(try!(tag.to_u64().map_err(|err| self.errat(err))))
+            }
+            _ => match try!(self.read_data_item(Some(first))) {
+                Cbor::Unsigned(v) => Event::Unsigned(v),
+                Cbor::Signed(v) => Event::Signed(v),
+                Cbor::Float(v) => Event::Float(v),
+                Cbor::Bool(v) => Event::Bool(v),
+                Cbor::Null => Event::Null,
+                Cbor::Undefined => Event::Undefined,
+                Cbor::Simple(v) => Event::Simple(v),
+                Cbor::Bytes(v) => Event::Bytes(v),
+                Cbor::Unicode(v) => Event::Unicode(v),
+                Cbor::Break => Event::Break,
+                // Only scalars and strings are read here.
+                _ => unreachable!(),
+            },
+        })
+    }
+
+    /// Read the header of an array and check that it has `len` elements.
+    ///
+    /// This is for hand written decoders that know the shape of their data.
+    /// The elements should then be read with, e.g., `items` or `decode`.
+    ///
+    /// An error is returned if the next data item isn't an array, or if it
+    /// has a definite length other than `len`. If the array has an
+    /// indefinite length, then `None` is returned and the caller should read
+    /// elements until a break.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{Decoder, Encoder};
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// enc.encode(&[(1, true, "a")]).unwrap();
+    ///
+    /// let mut dec = Decoder::from_bytes(enc.as_bytes());
+    /// assert_eq!(dec.expect_array(3).unwrap(), Some(3));
+    /// let n: u8 = dec.decode().next().unwrap().unwrap();
+    /// assert_eq!(n, 1);
+    /// ```
+    pub fn expect_array(&mut self, len: usize) -> CborResult<Option<usize>> {
+        self.expect_header(Type::Array, len)
+    }
+
+    /// Read the header of a map and check that it has `len` entries.
+    ///
+    /// This works just like `expect_array`. Each entry should then be read
+    /// as a key followed by a value.
+    pub fn expect_map(&mut self, len: usize) -> CborResult<Option<usize>> {
+        self.expect_header(Type::Map, len)
+    }
+
+    /// Decode an array with exactly `len` elements as the fields of a
+    /// positional record, such as a tuple struct.
+    ///
+    /// The array header is read and its length is checked, and then `f`
+    /// reads the fields one at a time with `TupleFields::field`. An error is
+    /// returned if the next data item isn't an array of length `len`, or if
+    /// `f` doesn't read exactly `len` fields. Indefinite length arrays are
+    /// accepted too, as long as they have `len` elements.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Decoder;
+    ///
+    /// struct Point(i32, i32, String);
+    ///
+    /// // [1, -2, "p"]
+    /// let mut dec = Decoder::from_bytes(vec![0x83, 0x01, 0x21, 0x61, 0x70]);
+    /// let p = dec.decode_tuple_struct(3, |fields| {
+    ///     Ok(Point(try!(fields.field()),
+    ///              try!(fields.field()),
+    ///              try!(fields.field())))
+    /// }).unwrap();
+    /// assert_eq!((p.0, p.1, p.2), (1, -2, "p".to_owned()));
+    /// ```
+    pub fn decode_tuple_struct<T, F>(&mut self, len: usize, f: F)
+                                    -> CborResult<T>
+            where F: FnOnce(&mut TupleFields<R>) -> CborResult<T> {
+        self.items_read = 0;
+        let definite = try!(self.expect_array(len)).is_some();
+        let (v, read) = {
+            let mut fields = TupleFields { dec: self, len: len, read: 0 };
+            let v = try!(f(&mut fields));
+            (v, fields.read)
+        };
+        if read < len {
+            return Err(self.errstr(format!(
+                "Only {} of the {} fields of a tuple struct were read.",
+                read, len)));
+        }
+        if !definite {
+            match try!(self.read_data_item(None)) {
+                Cbor::Break => {}
+                _ => return Err(self.errstr(format!(
+                    "Expected tuple struct with {} fields, but got more.",
+                    len))),
+            }
+        }
+        Ok(v)
+    }
+
+    /// Decode an array into a `Vec`, keeping the elements that were decoded
+    /// before any error.
+    ///
+    /// This is for best-effort recovery of truncated or corrupt data. The
+    /// elements are decoded one at a time, and the first error (e.g., an
+    /// `UnexpectedEOF` when the data is truncated in the middle of the
+    /// array) stops decoding. That error is returned along with every
+    /// element decoded before it. If the next data item isn't an array, no
+    /// elements are decoded. After an error, the position of the decoder in
+    /// the underlying reader is unspecified.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Decoder;
+    ///
+    /// // [1, 2, 3], cut off after the second element.
+    /// let mut dec = Decoder::from_bytes(vec![0x83, 0x01, 0x02]);
+    /// let (vals, err) = dec.decode_vec_partial::<u8>();
+    /// assert_eq!(vals, vec![1, 2]);
+    /// assert!(err.is_some());
+    /// ```
+    pub fn decode_vec_partial<D: Decodable>(&mut self)
+                                           -> (Vec<D>, Option<CborError>) {
+        self.items_read = 0;
+        let mut vals = vec![];
+        let len = match self.read_header(Type::Array) {
+            Ok(len) => len,
+            Err(err) => return (vals, Some(err)),
+        };
+        while len.map_or(true, |len| vals.len() < len) {
+            let v = match self.read_data_item(None) {
+                Ok(Cbor::Break) if len.is_none() => break,
+                Ok(v) => self.decode_value(v),
+                Err(err) => Err(err),
+            };
+            match v {
+                Ok(v) => vals.push(v),
+                Err(err) => return (vals, Some(err)),
+            }
+        }
+        (vals, None)
+    }
+
+    fn expect_header(&mut self, ty: Type, len: usize)
+                    -> CborResult<Option<usize>> {
+        match try!(self.read_header(ty)) {
+            Some(got) if got != len => Err(self.errstr(format!(
+                "Expected {:?} with length {}, but got length {}.",
+                ty, len, got))),
+            got => Ok(got),
+        }
+    }
+
+    /// Reads the header of an array or map and returns its length.
+    fn read_header(&mut self, ty: Type) -> CborResult<Option<usize>> {
+        let first = try!(self.read_first());
+        try!(self.count_item());
+        if (first & 0b111_00000) >> 5 != ty.major() {
+            let got = Type::from_desc(first).unwrap_or(Type::Any);
+            return Err(self.errat(ReadError::ty_mismatch(ty, got)));
+        }
+        self.read_len(first)
+    }
+
+    /// Reads the first byte of a data item, skipping any leading junk if
+    /// this is the start of the input.
+    fn read_first(&mut self) -> CborResult<u8> {
+        let mut first = try!(self.rdr.read_u8());
+        if !self.skip_leading_junk || self.rdr.bytes_read != 1 {
+            return Ok(first);
+        }
+        loop {
+            if first == 0xef {
+                let mut bom = [0; 2];
+                try!(self.rdr.read_full(&mut bom));
+                if bom != [0xbb, 0xbf] {
+                    return Err(self.errstr(format!(
+                        "Expected UTF-8 byte order mark, but got bytes \
+                         {:#x}, {:#x} after byte 0xef.", bom[0], bom[1])));
+                }
+            } else if !self.leading_junk.contains(&first) {
+                return Ok(first);
+            }
+            first = try!(self.rdr.read_u8());
+        }
+    }
+
     fn read_data_item(&mut self, first: Option<u8>) -> CborResult<Cbor> {
+        try!(self.count_item());
+        self.read_data_item_at(first)
+    }
+
+    /// Counts a data item (or header) towards `max_items`.
+    fn count_item(&mut self) -> CborResult<()> {
+        if self.items_read >= self.max_items {
+            return Err(self.errstr(format!(
+                "More than the maximum of {} data items.", self.max_items)));
+        }
+        self.items_read += 1;
+        Ok(())
+    }
+
+    fn read_data_item_at(&mut self, first: Option<u8>) -> CborResult<Cbor> {
         let first = match first {
             Some(first) => first,
-            None => try!(self.rdr.read_u8()),
+            None => try!(self.read_first()),
         };
         match (first & 0b111_00000) >> 5 {
             0 => self.read_uint(first).map(Cbor::Unsigned),
@@ -108,10 +727,10 @@ impl<R: io::Read> Decoder<R> {
             5 => self.read_map(first),
             6 => self.read_tag(first),
             7 => match first & 0b000_11111 {
-                v @ 0...23 => self.read_simple_value(v),
+                v @ 0...23 => self.read_simple_value(v, false),
                 24 => {
                     let b = try!(self.rdr.read_u8());
-                    self.read_simple_value(b)
+                    self.read_simple_value(b, true)
                 }
                 25...27 => self.read_float(first).map(Cbor::Float),
                 v @ 28...30 =>
@@ -128,11 +747,26 @@ impl<R: io::Read> Decoder<R> {
         }
     }
 
-    fn read_simple_value(&mut self, val: u8) -> CborResult<Cbor> {
+    /// Reads simple value `val`, where `long` is true if it was written in
+    /// the two byte form.
+    fn read_simple_value(&mut self, val: u8, long: bool)
+                        -> CborResult<Cbor> {
+        // The configured values take precedence over the standard ones, so
+        // that they can also be rearranged.
+        let map = self.simple_values;
+        if val == map.false_value {
+            return Ok(Cbor::Bool(false));
+        } else if val == map.true_value {
+            return Ok(Cbor::Bool(true));
+        } else if val == map.null_value {
+            return Ok(Cbor::Null);
+        }
         Ok(match val {
-            v @ 0...19 =>
+            // Values below 32 must be written in one byte.
+            v @ 0...19 if long =>
                 return Err(self.errat(
                     ReadError::Unassigned { major: 7, add: v })),
+            v @ 0...19 => Cbor::Simple(v),
             20 => Cbor::Bool(false),
             21 => Cbor::Bool(true),
             22 => Cbor::Null,
@@ -140,20 +774,42 @@ impl<R: io::Read> Decoder<R> {
             v @ 24...31 =>
                 return Err(self.errat(
                     ReadError::Reserved { major: 7, add: v })),
-            v /* 32...255 */ =>
-                return Err(self.errat(
-                    ReadError::Unassigned { major: 7, add: v })),
+            v /* 32...255 */ => Cbor::Simple(v),
         })
     }
 
     fn read_float(&mut self, first: u8) -> CborResult<CborFloat> {
+        let f = try!(self.read_float_raw(first));
+        if self.reject_nonfinite_floats && !f.to_f64().unwrap().is_finite() {
+            return Err(self.errstr(format!(
+                "Found non-finite float ({:?}), but non-finite floats are \
+                 rejected.", f)));
+        }
+        if self.reject_non_canonical {
+            // Only the width matters here, since NaN never compares equal.
+            let mut canonical = Cbor::Float(f);
+            canonical.canonicalize();
+            let shortest = match (canonical, f) {
+                (Cbor::Float(CborFloat::Float16(_)), CborFloat::Float16(_))
+                | (Cbor::Float(CborFloat::Float32(_)), CborFloat::Float32(_))
+                | (Cbor::Float(CborFloat::Float64(_)), CborFloat::Float64(_))
+                => true,
+                _ => false,
+            };
+            if !shortest {
+                return Err(self.errstr(format!(
+                    "Found non-canonical float {:?}.", f)));
+            }
+        }
+        Ok(f)
+    }
+
+    fn read_float_raw(&mut self, first: u8) -> CborResult<CborFloat> {
         Ok(match first & 0b000_11111 {
             25 => {
-                // Rust doesn't have a `f16` type, so just read a u16 and
-                // cast it to a u32 and then a f32.
-                // I think this is wrong. ---AG
+                // Rust doesn't have a `f16` type, so widen it to a f32.
                 let n = try!(self.rdr.read_u16::<BigEndian>());
-                CborFloat::Float16(unsafe { transmute(n as u32) })
+                CborFloat::Float16(half::to_f32(n))
             }
             26 => CborFloat::Float32(try!(self.rdr.read_f32::<BigEndian>())),
             27 => CborFloat::Float64(try!(self.rdr.read_f64::<BigEndian>())),
@@ -164,62 +820,236 @@ impl<R: io::Read> Decoder<R> {
     }
 
     fn read_tag(&mut self, first: u8) -> CborResult<Cbor> {
+        try!(self.count_tag());
         let tag = try!(self.read_uint(first));
         let tag = try!(tag.to_u64().map_err(|err| self.errat(err)));
-        let data = try!(self.read_data_item(None));
+        let mut data = try!(self.read_data_item(None));
+        if self.collapse_repeated_tags {
+            data = match data {
+                Cbor::Tag(inner) if inner.tag == tag => *inner.data,
+                data => data,
+            };
+        }
+        if tag == 0 && self.datetime_mode != DateTimeMode::Unchecked {
+            data = try!(self.check_datetime(data));
+        }
         Ok(Cbor::Tag(CborTag { tag: tag, data: Box::new(data) }))
     }
 
+    fn count_tag(&mut self) -> CborResult<()> {
+        if self.total_tags >= self.max_total_tags {
+            return Err(self.errstr(format!(
+                "More than the maximum of {} tags.", self.max_total_tags)));
+        }
+        self.total_tags += 1;
+        Ok(())
+    }
+
+    fn check_datetime(&self, data: Cbor) -> CborResult<Cbor> {
+        let lenient = self.datetime_mode == DateTimeMode::Lenient;
+        match data {
+            Cbor::Unicode(s) => match datetime::normalize(&s, lenient) {
+                Some(s) => Ok(Cbor::Unicode(s)),
+                None => Err(self.errstr(format!(
+                    "Invalid date/time string in tag 0: {:?}", s))),
+            },
+            data => Err(self.errat(ReadError::mismatch(Type::Unicode, &data))),
+        }
+    }
+
     fn read_map(&mut self, first: u8) -> CborResult<Cbor> {
         let len = try!(self.read_len(first));
-        let mut map = HashMap::with_capacity(len);
+        if let Some(len) = len {
+            try!(self.check_map_entries(len));
+        }
+        let cap = len.map_or(0, |len| cmp::min(100000, len));
+        let mut map = HashMap::with_capacity(cap);
         let at = self.rdr.bytes_read; // for coherent error reporting
-        for _ in 0..len {
+        let mut prev: Option<String> = None;
+        let mut i = 0;
+        while len.map_or(true, |len| i < len) {
             let key = match try!(self.read_data_item(None)) {
                 Cbor::Unicode(s) => s,
+                Cbor::Break if len.is_none() => break,
                 v => return Err(CborError::AtOffset {
                     kind: ReadError::mismatch(Type::Unicode, &v),
                     offset: at,
                 }),
             };
-            let val = try!(self.read_data_item(None));
-            map.insert(key, val);
+            try!(self.check_map_entries(i + 1));
+            let val = match try!(self.read_data_item(None)) {
+                Cbor::Break if len.is_none() => return Err(self.errstr(
+                    format!("Missing value for key '{}' in indefinite \
+                             length map.", key))),
+                val => val,
+            };
+            if self.reject_duplicate_keys && map.contains_key(&key) {
+                return Err(self.errstr(format!(
+                    "Duplicate key '{}' in map.", key)));
+            }
+            if self.reject_non_canonical {
+                if let Some(ref prev) = prev {
+                    if (prev.len(), prev) >= (key.len(), &key) {
+                        return Err(self.errstr(format!(
+                            "Key '{}' in map is not in canonical order.",
+                            key)));
+                    }
+                }
+                prev = Some(key.clone());
+            }
+            let absent = match val {
+                Cbor::Undefined => true,
+                Cbor::Tag(ref tag) => {
+                    tag.tag == 31 && *tag.data == Cbor::Undefined
+                }
+                _ => false,
+            };
+            if !(absent && self.drop_undefined_map_values) {
+                map.insert(key, val);
+            }
+            i += 1;
         }
         Ok(Cbor::Map(map))
     }
 
+    fn check_map_entries(&self, len: usize) -> CborResult<()> {
+        if len > self.max_map_entries {
+            return Err(self.errstr(format!(
+                "Map with more than {} entries exceeds the maximum.",
+                self.max_map_entries)));
+        }
+        Ok(())
+    }
+
     fn read_array(&mut self, first: u8) -> CborResult<Cbor> {
         let len = try!(self.read_len(first));
-        let mut array = Vec::with_capacity(cmp::min(100000, len));
-        for _ in 0..len {
-            let v = try!(self.read_data_item(None));
-            array.push(v);
+        let cap = len.map_or(0, |len| cmp::min(100000, len));
+        let mut array = Vec::with_capacity(cap);
+        let mut i = 0;
+        while len.map_or(true, |len| i < len) {
+            match try!(self.read_data_item(None)) {
+                Cbor::Break if len.is_none() => break,
+                v => array.push(v),
+            }
+            i += 1;
         }
         Ok(Cbor::Array(array))
     }
 
     fn read_string(&mut self, first: u8) -> CborResult<Cbor> {
-        let len = try!(self.read_len(first));
-        let mut buf = vec_from_elem(len, 0u8);
-        try!(self.rdr.read_full(&mut buf));
+        let buf = match try!(self.read_len(first)) {
+            Some(len) => try!(self.read_chunk(len)),
+            None => try!(self.read_chunks(3)),
+        };
         String::from_utf8(buf)
                .map(Cbor::Unicode)
                .map_err(|err| self.errstr(err.utf8_error().to_string()))
     }
 
     fn read_bytes(&mut self, first: u8) -> CborResult<Cbor> {
-        let len = try!(self.read_len(first));
-        let mut buf = vec_from_elem(len, 0u8);
-        try!(self.rdr.read_full(&mut buf));
+        let buf = match try!(self.read_len(first)) {
+            Some(len) => try!(self.read_chunk(len)),
+            None => try!(self.read_chunks(2)),
+        };
         Ok(Cbor::Bytes(CborBytes(buf)))
     }
 
-    fn read_len(&mut self, first: u8) -> CborResult<usize> {
+    fn read_chunk(&mut self, len: usize) -> CborResult<Vec<u8>> {
+        try!(self.check_string_len(0, len));
+        // The length can't be trusted, so don't allocate all of it upfront.
+        // The buffer grows as bytes are actually read instead.
+        let mut buf = Vec::with_capacity(cmp::min(len, 64 * 1024));
+        try!((&mut self.rdr).take(len as u64).read_to_end(&mut buf));
+        if buf.len() < len {
+            return Err(CborError::UnexpectedEOF);
+        }
+        Ok(buf)
+    }
+
+    fn check_string_len(&self, have: usize, len: usize) -> CborResult<()> {
+        if len > self.max_string_len - cmp::min(have, self.max_string_len) {
+            return Err(self.errstr(format!(
+                "String of length {} exceeds the maximum of {}.",
+                have.saturating_add(len), self.max_string_len)));
+        }
+        Ok(())
+    }
+
+    /// Reads the chunks of an indefinite length string with the given major
+    /// type, up to and including the terminating break.
+    fn read_chunks(&mut self, major: u8) -> CborResult<Vec<u8>> {
+        let mut buf = vec![];
+        loop {
+            let first = try!(self.rdr.read_u8());
+            if first == 0xff {
+                return Ok(buf);
+            }
+            if (first & 0b111_00000) >> 5 != major
+                    || first & 0b000_11111 == 31 {
+                return Err(self.errstr(format!(
+                    "Expected definite length chunk with major type {} in \
+                     indefinite length string, but got byte {:#x}.",
+                    major, first)));
+            }
+            // Indefinite length chunks were ruled out above.
+            let len = try!(self.read_len(first)).unwrap();
+            try!(self.check_string_len(buf.len(), len));
+            let chunk = try!(self.read_chunk(len));
+            // Each chunk of a Unicode string must itself be valid UTF-8.
+            if major == 3 {
+                if let Err(err) = ::std::str::from_utf8(&chunk) {
+                    return Err(self.errstr(err.to_string()));
+                }
+            }
+            buf.extend(chunk);
+        }
+    }
+
+    /// Reads the length of a string, array or map, or `None` if it has an
+    /// indefinite length.
+    fn read_len(&mut self, first: u8) -> CborResult<Option<usize>> {
+        if first & 0b000_11111 == 31 {
+            if self.forbid_indefinite || self.reject_non_canonical {
+                let ty = Type::from_desc(first).unwrap_or(Type::Any);
+                return Err(self.errstr(format!(
+                    "Found indefinite length {:?}, but indefinite length \
+                     data items are forbidden.", ty)));
+            }
+            return Ok(None);
+        }
         self.read_uint(first)
             .and_then(|v| v.to_usize().map_err(|err| self.errat(err)))
+            .map(Some)
     }
 
     fn read_uint(&mut self, first: u8) -> CborResult<CborUnsigned> {
+        let v = try!(self.read_uint_raw(first));
+        try!(self.check_shortest(first, v.to_u64().unwrap()));
+        Ok(v)
+    }
+
+    /// When non-canonical data is rejected, fails unless the argument `n`
+    /// of the header `first` uses the shortest possible encoding.
+    fn check_shortest(&self, first: u8, n: u64) -> CborResult<()> {
+        if !self.reject_non_canonical {
+            return Ok(());
+        }
+        let shortest = match first & 0b000_11111 {
+            24 => n >= 24,
+            25 => n > ::std::u8::MAX as u64,
+            26 => n > ::std::u16::MAX as u64,
+            27 => n > ::std::u32::MAX as u64,
+            _ => true,
+        };
+        if !shortest {
+            return Err(self.errstr(format!(
+                "Found non-canonical encoding of {} in header {:#x}.",
+                n, first)));
+        }
+        Ok(())
+    }
+
+    fn read_uint_raw(&mut self, first: u8) -> CborResult<CborUnsigned> {
         Ok(match first & 0b000_11111 {
             n @ 0...23 => CborUnsigned::UInt8(n),
             24 => CborUnsigned::UInt8(try!(self.rdr.read_u8())),
@@ -236,6 +1066,7 @@ impl<R: io::Read> Decoder<R> {
             n @ 0...23 => CborSigned::Int8(-1 - (n as i8)),
             24 => {
                 let n = try!(self.rdr.read_u8());
+                try!(self.check_shortest(first, n as u64));
                 if n > ::std::i8::MAX as u8 {
                     CborSigned::Int16(-1 - (n as i16))
                 } else {
@@ -244,6 +1075,7 @@ impl<R: io::Read> Decoder<R> {
             }
             25 => {
                 let n = try!(self.rdr.read_u16::<BigEndian>());
+                try!(self.check_shortest(first, n as u64));
                 if n > ::std::i16::MAX as u16 {
                     CborSigned::Int32(-1 - (n as i32))
                 } else {
@@ -252,6 +1084,7 @@ impl<R: io::Read> Decoder<R> {
             }
             26 => {
                 let n = try!(self.rdr.read_u32::<BigEndian>());
+                try!(self.check_shortest(first, n as u64));
                 if n > ::std::i32::MAX as u32 {
                     CborSigned::Int64(-1 - (n as i64))
                 } else {
@@ -260,6 +1093,7 @@ impl<R: io::Read> Decoder<R> {
             }
             27 => {
                 let n = try!(self.rdr.read_u64::<BigEndian>());
+                try!(self.check_shortest(first, n as u64));
                 if n > ::std::i64::MAX as u64 {
                     return Err(self.errstr(format!(
                         "Negative integer out of range: {:?}", n)));
@@ -278,7 +1112,174 @@ impl Decoder<io::Cursor<Vec<u8>>> {
     /// The buffer is usually given as either a `Vec<u8>` or a `&[u8]`.
     pub fn from_bytes<T>(bytes: T) -> Decoder<io::Cursor<Vec<u8>>>
             where T: Into<Vec<u8>> {
-        Decoder { rdr: CborReader::new(io::Cursor::new(bytes.into())) }
+        Decoder::new(CborReader::new(io::Cursor::new(bytes.into())))
+    }
+
+    /// Returns the bytes that haven't been read yet.
+    ///
+    /// After decoding a data item, this is everything after it. Note that
+    /// after an error, the position in the buffer is unspecified.
+    pub fn remaining(&self) -> &[u8] {
+        let buf = self.rdr.rdr.get_ref();
+        let pos = cmp::min(self.rdr.rdr.position(), buf.len() as u64);
+        &buf[pos as usize..]
+    }
+
+    /// Returns a copy of the bytes that haven't been read yet, e.g., to
+    /// decode them later with another decoder.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Decoder;
+    ///
+    /// let mut dec = Decoder::from_bytes(vec![0x01, 0x82, 0x02, 0x03]);
+    /// assert_eq!(dec.decode::<u8>().next().unwrap().unwrap(), 1);
+    /// let rest = dec.clone_remaining();
+    /// assert_eq!(rest, vec![0x82, 0x02, 0x03]);
+    /// ```
+    pub fn clone_remaining(&self) -> Vec<u8> {
+        self.remaining().to_vec()
+    }
+
+    /// Read a single frame of a length prefixed protocol from `rdr` and
+    /// decode it into a Rust value.
+    ///
+    /// A frame is a 4 byte big-endian length followed by exactly that many
+    /// bytes, which must contain exactly one CBOR data item. If `rdr` ends
+    /// before the frame does, an `UnexpectedEOF` error is returned. Nothing
+    /// past the end of the frame is read, so frames can be read one after
+    /// the other from the same reader.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Decoder;
+    ///
+    /// let frame = vec![0, 0, 0, 3, 0x82, 0x01, 0x02];
+    /// let v: Vec<u8> = Decoder::decode_length_prefixed(&mut &frame[..])
+    ///                           .unwrap();
+    /// assert_eq!(v, vec![1, 2]);
+    /// ```
+    pub fn decode_length_prefixed<D: Decodable>(rdr: &mut impl io::Read)
+                                               -> CborResult<D> {
+        let len = try!(rdr.read_u32::<BigEndian>()) as usize;
+        // The length can't be trusted, so don't allocate all of it upfront.
+        let mut buf = Vec::with_capacity(cmp::min(len, 64 * 1024));
+        try!(rdr.take(len as u64).read_to_end(&mut buf));
+        if buf.len() < len {
+            return Err(CborError::UnexpectedEOF);
+        }
+        let mut dec = Decoder::from_bytes(buf);
+        let v = match dec.decode().next() {
+            Some(v) => try!(v),
+            None => return Err(CborError::UnexpectedEOF),
+        };
+        if dec.rdr.bytes_read < len {
+            return Err(dec.errstr(format!(
+                "Found trailing data after the data item in a frame of {} \
+                 bytes.", len)));
+        }
+        Ok(v)
+    }
+}
+
+#[cfg(feature = "bytes")]
+impl Decoder<io::Cursor<Bytes>> {
+    /// Create a new CBOR decoder that reads from a shared `bytes::Bytes`
+    /// buffer.
+    ///
+    /// This decodes like any other decoder, but byte strings can also be
+    /// read without copying them with `read_shared_bytes`.
+    pub fn from_shared_bytes(bytes: Bytes) -> Decoder<io::Cursor<Bytes>> {
+        Decoder::new(CborReader::new(io::Cursor::new(bytes)))
+    }
+
+    /// Read the next data item, which must be a byte string, as a slice of
+    /// the underlying buffer.
+    ///
+    /// For a definite length byte string, the returned `Bytes` shares the
+    /// buffer that this decoder reads from, so no bytes are copied. The
+    /// chunks of an indefinite length byte string have to be joined, so
+    /// they are copied into a new buffer. Any other data item is an error.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// # extern crate bytes;
+    /// # extern crate cbor;
+    /// # fn main() {
+    /// use bytes::Bytes;
+    /// use cbor::Decoder;
+    ///
+    /// // [h'0102', h'03']
+    /// let buf = Bytes::from(vec![0x82, 0x42, 0x01, 0x02, 0x41, 0x03]);
+    /// let mut dec = Decoder::from_shared_bytes(buf.clone());
+    /// dec.expect_array(2).unwrap();
+    /// let first = dec.read_shared_bytes().unwrap();
+    /// assert_eq!(&first[..], &[1, 2]);
+    /// assert_eq!(first.as_ptr(), buf[2..].as_ptr());
+    /// assert_eq!(&dec.read_shared_bytes().unwrap()[..], &[3]);
+    /// # }
+    /// ```
+    pub fn read_shared_bytes(&mut self) -> CborResult<Bytes> {
+        let len = match try!(self.read_header(Type::Bytes)) {
+            Some(len) => len,
+            None => return self.read_chunks(2).map(Bytes::from),
+        };
+        try!(self.check_string_len(0, len));
+        let start = self.rdr.rdr.position() as usize;
+        let buf = self.rdr.rdr.get_ref().clone();
+        if len > buf.len() - cmp::min(start, buf.len()) {
+            return Err(CborError::UnexpectedEOF);
+        }
+        self.rdr.rdr.set_position((start + len) as u64);
+        self.rdr.last_offset = self.rdr.bytes_read;
+        self.rdr.bytes_read += len;
+        Ok(buf.slice(start..start + len))
+    }
+}
+
+impl<R: io::Read + io::Seek> Decoder<io::BufReader<R>> {
+    /// Create a new CBOR decoder from an underlying reader that can seek.
+    ///
+    /// This is just like `from_reader`, except the decoder can also jump to
+    /// any data item with `decode_at`.
+    pub fn from_seekable(rdr: R) -> Decoder<io::BufReader<R>> {
+        Decoder::from_reader(rdr)
+    }
+}
+
+impl<R: io::Read + io::Seek> Decoder<R> {
+    /// Seek to the top-level data item starting at `offset` bytes from the
+    /// start of the underlying reader and decode it into a Rust value.
+    ///
+    /// The offsets of data items can be found with `split_sequence`.
+    /// Afterwards, decoding continues from the end of this data item.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::io::Cursor;
+    /// use cbor::{Decoder, Encoder, split_sequence};
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// enc.encode(&["first", "second"]).unwrap();
+    /// let bytes = enc.into_bytes();
+    /// let offsets = split_sequence(&bytes).unwrap();
+    ///
+    /// let mut dec = Decoder::from_seekable(Cursor::new(bytes));
+    /// let second: String = dec.decode_at(offsets[1].start as u64).unwrap();
+    /// assert_eq!(second, "second");
+    /// ```
+    pub fn decode_at<D: Decodable>(&mut self, offset: u64) -> CborResult<D> {
+        try!(self.rdr.rdr.seek(io::SeekFrom::Start(offset)));
+        // Offsets in errors are relative to the start of the reader.
+        self.rdr.last_offset = offset as usize;
+        self.rdr.bytes_read = offset as usize;
+        self.items_read = 0;
+        let v = try!(self.read_data_item(None));
+        self.decode_value(v)
     }
 }
 
@@ -290,6 +1291,20 @@ impl<R: io::Read> Decoder<R> {
     fn errstr(&self, s: String) -> CborError {
         self.errat(ReadError::Other(s))
     }
+
+    /// Returns the number of bytes read from the underlying reader so far.
+    pub(crate) fn bytes_read(&self) -> usize {
+        self.rdr.bytes_read
+    }
+
+    /// Decodes a data item that has already been read into a Rust value.
+    fn decode_value<D: Decodable>(&self, v: Cbor) -> CborResult<D> {
+        let mut dec = CborDecoder::new(v);
+        dec.set_accept_array_as_map(self.accept_array_as_map);
+        dec.set_accept_field0_maps(self.accept_field0_maps);
+        dec.set_strict_floats(self.strict_floats);
+        Decodable::decode(&mut dec)
+    }
 }
 
 /// An iterator over items decoded from CBOR into Rust values.
@@ -306,7 +1321,7 @@ impl<'a, R: io::Read, D: Decodable> Iterator for DecodedItems<'a, R, D> {
 
     fn next(&mut self) -> Option<CborResult<D>> {
         self.it.next().map(|result| {
-            result.and_then(|v| Decodable::decode(&mut CborDecoder::new(v)))
+            result.and_then(|v| self.it.dec.decode_value(v))
         })
     }
 }
@@ -323,6 +1338,7 @@ impl<'a, R: io::Read> Iterator for Items<'a, R> {
     type Item = CborResult<Cbor>;
 
     fn next(&mut self) -> Option<CborResult<Cbor>> {
+        self.dec.items_read = 0;
         match self.dec.read_data_item(None) {
             Err(ref err) if err.is_eof() => None,
             Err(err) => Some(Err(err)),
@@ -331,6 +1347,144 @@ impl<'a, R: io::Read> Iterator for Items<'a, R> {
     }
 }
 
+/// An iterator over the entries of a single map, decoded into Rust values.
+///
+/// `K` and `V` are the types of the keys and values, `R` represents the
+/// underlying reader and `'a` is the lifetime of the decoder.
+pub struct MapEntries<'a, R: 'a, K, V> {
+    dec: &'a mut Decoder<R>,
+    // `None` until the map header has been read, then the number of entries
+    // left (or `None` inside if the map has an indefinite length).
+    left: Option<Option<usize>>,
+    done: bool,
+    _phantom: ::std::marker::PhantomData<(K, V)>,
+}
+
+impl<'a, R: io::Read, K, V> MapEntries<'a, R, K, V>
+        where K: Decodable, V: Decodable {
+    fn next_entry(&mut self) -> CborResult<Option<(K, V)>> {
+        let left = match self.left {
+            Some(left) => left,
+            None => {
+                let first = try!(self.dec.read_first());
+                try!(self.dec.count_item());
+                if (first & 0b111_00000) >> 5 != 5 {
+                    let got = Type::from_desc(first).unwrap_or(Type::Any);
+                    return Err(self.dec.errat(
+                        ReadError::ty_mismatch(Type::Map, got)));
+                }
+                try!(self.dec.read_len(first))
+            }
+        };
+        let key = match left {
+            Some(0) => return Ok(None),
+            Some(_) => try!(self.dec.read_data_item(None)),
+            None => match try!(self.dec.read_data_item(None)) {
+                Cbor::Break => return Ok(None),
+                key => key,
+            },
+        };
+        self.left = Some(left.map(|n| n - 1));
+        let val = try!(self.dec.read_data_item(None));
+        let key = try!(self.dec.decode_value(key));
+        let val = try!(self.dec.decode_value(val));
+        Ok(Some((key, val)))
+    }
+}
+
+impl<'a, R: io::Read, K, V> Iterator for MapEntries<'a, R, K, V>
+        where K: Decodable, V: Decodable {
+    type Item = CborResult<(K, V)>;
+
+    fn next(&mut self) -> Option<CborResult<(K, V)>> {
+        if self.done {
+            return None;
+        }
+        match self.next_entry() {
+            Ok(Some(entry)) => Some(Ok(entry)),
+            Ok(None) => { self.done = true; None }
+            Err(err) => { self.done = true; Some(Err(err)) }
+        }
+    }
+}
+
+/// The fields of a positional record being decoded with
+/// `Decoder::decode_tuple_struct`.
+///
+/// `R` represents the underlying reader and `'a` is the lifetime of the
+/// decoder.
+pub struct TupleFields<'a, R: 'a> {
+    dec: &'a mut Decoder<R>,
+    len: usize,
+    read: usize,
+}
+
+impl<'a, R: io::Read> TupleFields<'a, R> {
+    /// Decode the next field into a Rust value.
+    ///
+    /// An error is returned if all of the fields have been read already.
+    pub fn field<D: Decodable>(&mut self) -> CborResult<D> {
+        if self.read == self.len {
+            return Err(self.dec.errstr(format!(
+                "Tried to read more than the {} fields of a tuple struct.",
+                self.len)));
+        }
+        let v = match try!(self.dec.read_data_item(None)) {
+            Cbor::Break => return Err(self.dec.errstr(format!(
+                "Expected tuple struct with {} fields, but got {}.",
+                self.len, self.read))),
+            v => v,
+        };
+        self.read += 1;
+        self.dec.decode_value(v)
+    }
+}
+
+/// An iterator over CBOR events.
+///
+/// `R` represents the underlying reader and `'a` is the lifetime of the
+/// decoder.
+pub struct Events<'a, R: 'a> {
+    dec: &'a mut Decoder<R>,
+}
+
+impl<'a, R: io::Read> Iterator for Events<'a, R> {
+    type Item = CborResult<Event>;
+
+    fn next(&mut self) -> Option<CborResult<Event>> {
+        match self.dec.read_event() {
+            Err(ref err) if err.is_eof() => None,
+            Err(err) => Some(Err(err)),
+            Ok(v) => Some(Ok(v)),
+        }
+    }
+}
+
+/// An iterator over CBOR items in terms of the abstract syntax, paired with
+/// the bytes each item was read from.
+///
+/// `R` represents the underlying reader and `'a` is the lifetime of the
+/// decoder.
+pub struct RawItems<'a, R: 'a> {
+    dec: &'a mut Decoder<R>,
+}
+
+impl<'a, R: io::Read> Iterator for RawItems<'a, R> {
+    type Item = CborResult<(Vec<u8>, Cbor)>;
+
+    fn next(&mut self) -> Option<CborResult<(Vec<u8>, Cbor)>> {
+        self.dec.rdr.capture = Some(vec![]);
+        self.dec.items_read = 0;
+        let result = self.dec.read_data_item(None);
+        let raw = self.dec.rdr.capture.take().unwrap_or(vec![]);
+        match result {
+            Err(ref err) if err.is_eof() && raw.is_empty() => None,
+            Err(err) => Some(Err(err)),
+            Ok(v) => Some(Ok((raw, v))),
+        }
+    }
+}
+
 /// A very light layer over a basic reader that keeps track of offset
 /// information at the byte level.
 struct CborReader<R> {
@@ -338,6 +1492,8 @@ struct CborReader<R> {
     // used for error reporting
     last_offset: usize,
     bytes_read: usize,
+    // when set, every byte read is also appended here
+    capture: Option<Vec<u8>>,
 }
 
 impl<R: io::Read> io::Read for CborReader<R> {
@@ -345,6 +1501,9 @@ impl<R: io::Read> io::Read for CborReader<R> {
         let n = try!(self.rdr.read(buf));
         self.last_offset = self.bytes_read;
         self.bytes_read += n;
+        if let Some(ref mut capture) = self.capture {
+            capture.extend_from_slice(&buf[..n]);
+        }
         Ok(n)
     }
 }
@@ -355,6 +1514,7 @@ impl<R: io::Read> CborReader<R> {
             rdr: rdr,
             last_offset: 0,
             bytes_read: 0,
+            capture: None,
         }
     }
 
@@ -369,10 +1529,3 @@ impl<R: io::Read> CborReader<R> {
         Ok(())
     }
 }
-
-fn vec_from_elem<T: Copy>(len: usize, v: T) -> Vec<T> {
-    let mut xs = Vec::with_capacity(len);
-    unsafe { xs.set_len(len); }
-    for x in &mut xs { *x = v; }
-    xs
-}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+
+use Cbor;
+
+/// One step in the path from the root of a `Cbor` value to a nested value.
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum PathSegment {
+    /// The value of a map entry with this key.
+    Key(String),
+    /// The element of an array at this index.
+    Index(usize),
+}
+
+/// A single structural difference between two `Cbor` values.
+///
+/// The path is relative to the values given to `diff`, and is empty if the
+/// values themselves differ.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Change {
+    /// A map entry or array element that only the second value has.
+    Added {
+        /// Where the value was added.
+        path: Vec<PathSegment>,
+        /// The added value.
+        value: Cbor,
+    },
+    /// A map entry or array element that only the first value has.
+    Removed {
+        /// Where the value was removed.
+        path: Vec<PathSegment>,
+        /// The removed value.
+        value: Cbor,
+    },
+    /// A value that is different in the second value.
+    Changed {
+        /// Where the value changed.
+        path: Vec<PathSegment>,
+        /// The value in the first value.
+        old: Cbor,
+        /// The value in the second value.
+        new: Cbor,
+    },
+}
+
+/// Returns the structural differences between `a` and `b`.
+///
+/// Maps are compared key by key and arrays are compared index by index, so
+/// inserting an element at the front of an array shows up as a change to
+/// every element after it. Tags with the same tag number are compared by
+/// their data items. Any other pair of values that aren't equal is reported
+/// as a single change.
+///
+/// Changes are ordered by path, with map keys in sorted order, so the result
+/// is deterministic.
+///
+/// # Example
+///
+/// ```rust
+/// use cbor::{Change, Decoder, PathSegment, diff};
+///
+/// // {"a": 1} and {"a": 2}
+/// let a = Decoder::from_bytes(vec![0xa1, 0x61, 0x61, 0x01])
+///                 .items().next().unwrap().unwrap();
+/// let b = Decoder::from_bytes(vec![0xa1, 0x61, 0x61, 0x02])
+///                 .items().next().unwrap().unwrap();
+/// match diff(&a, &b)[0] {
+///     Change::Changed { ref path, .. } => {
+///         assert_eq!(path, &[PathSegment::Key("a".to_string())]);
+///     }
+///     _ => unreachable!(),
+/// }
+/// ```
+pub fn diff(a: &Cbor, b: &Cbor) -> Vec<Change> {
+    let mut changes = vec![];
+    diff_at(&mut vec![], a, b, &mut changes);
+    changes
+}
+
+fn diff_at(
+    path: &mut Vec<PathSegment>,
+    a: &Cbor,
+    b: &Cbor,
+    changes: &mut Vec<Change>,
+) {
+    match (a, b) {
+        (&Cbor::Map(ref a), &Cbor::Map(ref b)) => {
+            diff_maps(path, a, b, changes);
+        }
+        (&Cbor::Array(ref a), &Cbor::Array(ref b)) => {
+            for (i, (a, b)) in a.iter().zip(b).enumerate() {
+                path.push(PathSegment::Index(i));
+                diff_at(path, a, b, changes);
+                path.pop();
+            }
+            for (i, a) in a.iter().enumerate().skip(b.len()) {
+                changes.push(Change::Removed {
+                    path: at(path, PathSegment::Index(i)),
+                    value: a.clone(),
+                });
+            }
+            for (i, b) in b.iter().enumerate().skip(a.len()) {
+                changes.push(Change::Added {
+                    path: at(path, PathSegment::Index(i)),
+                    value: b.clone(),
+                });
+            }
+        }
+        (&Cbor::Tag(ref a), &Cbor::Tag(ref b)) if a.tag == b.tag => {
+            diff_at(path, &a.data, &b.data, changes);
+        }
+        (a, b) => {
+            if a != b {
+                changes.push(Change::Changed {
+                    path: path.clone(),
+                    old: a.clone(),
+                    new: b.clone(),
+                });
+            }
+        }
+    }
+}
+
+fn diff_maps(
+    path: &mut Vec<PathSegment>,
+    a: &HashMap<String, Cbor>,
+    b: &HashMap<String, Cbor>,
+    changes: &mut Vec<Change>,
+) {
+    let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+    keys.sort();
+    keys.dedup();
+    for key in keys {
+        let seg = PathSegment::Key(key.clone());
+        match (a.get(key), b.get(key)) {
+            (Some(a), Some(b)) => {
+                path.push(seg);
+                diff_at(path, a, b, changes);
+                path.pop();
+            }
+            (Some(a), None) => changes.push(Change::Removed {
+                path: at(path, seg),
+                value: a.clone(),
+            }),
+            (None, Some(b)) => changes.push(Change::Added {
+                path: at(path, seg),
+                value: b.clone(),
+            }),
+            (None, None) => unreachable!(),
+        }
+    }
+}
+
+fn at(path: &[PathSegment], seg: PathSegment) -> Vec<PathSegment> {
+    let mut path = path.to_vec();
+    path.push(seg);
+    path
+}
@@ -1,5 +1,8 @@
 use std::iter::IntoIterator;
 use std::io;
+use std::i8;
+use std::i16;
+use std::i32;
 use std::u8;
 use std::u16;
 use std::u32;
@@ -8,56 +11,160 @@ use byteorder::{ByteOrder, BigEndian};
 use rustc_serialize::Encodable;
 use rustc_serialize::Encoder as RustcEncoder;
 
-use {CborError, CborResult, Type, WriteError};
+use half;
+use {
+    Cbor, CborBytes, CborError, CborFloat, CborResult, CborSigned,
+    CborUnsigned, SimpleValues, Type, WriteError,
+};
 
 /// Encodes Rust values to CBOR bytes in the underlying writer `W`.
 ///
 /// Note that currently, using the serialization infrastructure is the only
 /// way to write CBOR in this crate.
+///
+/// Structs are written as maps from field name to value, except for tuple
+/// structs with exactly one field (i.e., newtypes), which are written exactly
+/// like the value they wrap.
 pub struct Encoder<W> {
     buf: W,
     emitting_key: bool,
     byte_string: bool,
     tag: bool,
+    tag_number: bool,
+    newtype: bool,
+    forbid_floats: bool,
+    unit_variants_as_index: bool,
+    compact_enums: bool,
+    sort_map_keys: bool,
+    structs_as_arrays: bool,
+    fixed_int_width: Option<IntWidth>,
+    simple_values: SimpleValues,
+    // Whether the fields of the innermost struct are written positionally.
+    positional: bool,
+    // When not empty, bytes are written to the last buffer instead of `buf`.
+    captures: Vec<Vec<u8>>,
+    // The encoded entries of each map being sorted, innermost last.
+    sorted_maps: Vec<Vec<(Vec<u8>, Vec<u8>)>>,
 }
 
 impl<W: io::Write> Encoder<W> {
     fn write_num(&mut self, major: u8, n: u64) -> CborResult<()> {
         let major = major << 5;
         if n <= 23 {
-            fromerr!(self.buf.write_all(&[major | n as u8]))
+            self.write_all(&[major | n as u8])
         } else if n <= u8::MAX as u64 {
-            fromerr!(self.buf.write_all(&[major | 24, n as u8]))
+            self.write_all(&[major | 24, n as u8])
         } else if n <= u16::MAX as u64 {
             let mut buf = [major | 25, 0, 0];
             <BigEndian as ByteOrder>::write_u16(&mut buf[1..], n as u16);
-            fromerr!(self.buf.write_all(&buf))
+            self.write_all(&buf)
         } else if n <= u32::MAX as u64 {
             let mut buf = [major | 26, 0, 0, 0, 0];
             <BigEndian as ByteOrder>::write_u32(&mut buf[1..], n as u32);
-            fromerr!(self.buf.write_all(&buf))
+            self.write_all(&buf)
         } else {
             let mut buf = [major | 27, 0, 0, 0, 0, 0, 0, 0, 0];
             <BigEndian as ByteOrder>::write_u64(&mut buf[1..], n);
-            fromerr!(self.buf.write_all(&buf))
+            self.write_all(&buf)
+        }
+    }
+
+    /// Writes a header with an explicit additional information value of 24,
+    /// 25, 26 or 27, so that `n` takes up exactly 1, 2, 4 or 8 bytes.
+    fn write_num_fixed(&mut self, major: u8, add: u8, n: u64)
+                      -> CborResult<()> {
+        let mut buf = [(major << 5) | add, 0, 0, 0, 0, 0, 0, 0, 0];
+        let len = match add {
+            24 => { buf[1] = n as u8; 2 }
+            25 => {
+                <BigEndian as ByteOrder>::write_u16(&mut buf[1..], n as u16);
+                3
+            }
+            26 => {
+                <BigEndian as ByteOrder>::write_u32(&mut buf[1..], n as u32);
+                5
+            }
+            _ => {
+                <BigEndian as ByteOrder>::write_u64(&mut buf[1..], n);
+                9
+            }
+        };
+        self.write_all(&buf[..len])
+    }
+
+    fn write_all(&mut self, bytes: &[u8]) -> CborResult<()> {
+        match self.captures.last_mut() {
+            Some(capture) => { capture.extend_from_slice(bytes); Ok(()) }
+            None => fromerr!(self.buf.write_all(bytes)),
+        }
+    }
+
+    /// Runs `f` and returns everything it wrote instead of writing it.
+    fn capture<F>(&mut self, f: F) -> CborResult<Vec<u8>>
+            where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
+        self.captures.push(vec![]);
+        let r = f(self);
+        let bytes = self.captures.pop().unwrap();
+        r.map(|_| bytes)
+    }
+
+    fn emit_map_elt_key_raw<F>(&mut self, f: F) -> CborResult<()>
+            where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
+        self.emitting_key = true;
+        let r = f(self);
+        self.emitting_key = false;
+        r
+    }
+
+    fn check_float(&self, ty: Type) -> CborResult<()> {
+        if self.forbid_floats {
+            return Err(CborError::Encode(WriteError::Other(format!(
+                "Found float ({:?}), but floats are forbidden.", ty))));
+        }
+        Ok(())
+    }
+
+    fn write_simple(&mut self, v: u8) -> CborResult<()> {
+        match v {
+            0...23 => self.write_all(&[(7 << 5) | v]),
+            24...31 => Err(CborError::Encode(WriteError::Other(format!(
+                "Invalid simple value {}.", v)))),
+            _ => self.write_all(&[(7 << 5) | 24, v]),
         }
     }
 
     fn write_uint(&mut self, n: u64) -> CborResult<()> {
-        self.write_num(0, n)
+        self.write_int_arg(0, n)
     }
 
     fn write_int(&mut self, n: i64) -> CborResult<()> {
         if n >= 0 {
             self.write_uint(n as u64)
         } else {
-            self.write_num(1, (-1 - n) as u64)
+            self.write_int_arg(1, (-1 - n) as u64)
         }
     }
 
-    fn is_tag_field(&self, name: &str) -> bool {
-        self.tag && name.starts_with("__cbor_tag_encode")
+    /// Writes the header of an integer, in the fixed width if one is set.
+    fn write_int_arg(&mut self, major: u8, n: u64) -> CborResult<()> {
+        let width = match self.fixed_int_width {
+            None => return self.write_num(major, n),
+            Some(width) => width,
+        };
+        let (add, max) = match width {
+            IntWidth::Bits8 => (24, u8::MAX as u64),
+            IntWidth::Bits16 => (25, u16::MAX as u64),
+            IntWidth::Bits32 => (26, u32::MAX as u64),
+            IntWidth::Bits64 => (27, ::std::u64::MAX),
+        };
+        if n > max {
+            return Err(CborError::Encode(WriteError::Other(format!(
+                "Integer {}{} does not fit in the fixed width {:?}.",
+                if major == 1 { "-1-" } else { "" }, n, width))));
+        }
+        self.write_num_fixed(major, add, n)
     }
+
 }
 
 impl<W: io::Write> Encoder<W> {
@@ -72,6 +179,18 @@ impl<W: io::Write> Encoder<W> {
             emitting_key: false,
             byte_string: false,
             tag: false,
+            tag_number: false,
+            newtype: false,
+            forbid_floats: false,
+            unit_variants_as_index: false,
+            compact_enums: false,
+            sort_map_keys: false,
+            structs_as_arrays: false,
+            fixed_int_width: None,
+            simple_values: SimpleValues::default(),
+            positional: false,
+            captures: vec![],
+            sorted_maps: vec![],
         }
     }
 
@@ -83,6 +202,12 @@ impl<W: io::Write> Encoder<W> {
     /// Note that this encodes top-level CBOR data items. They can be decoded
     /// in a streaming fashion.
     ///
+    /// In other words, this writes a CBOR sequence (RFC 8742): each value is
+    /// written right after the previous one, without an enclosing array.
+    /// Calling `encode` again continues the same sequence, which is how to
+    /// write values of different types. `Decoder::decode` reads a sequence
+    /// back. (`encode_seq` does the same thing under a more explicit name.)
+    ///
     /// # Example
     ///
     /// Encode a list of numbers.
@@ -103,10 +228,348 @@ impl<W: io::Write> Encoder<W> {
         Ok(())
     }
 
+    /// Encode each item of `items` as a top-level data item, forming a CBOR
+    /// sequence (RFC 8742).
+    ///
+    /// This is the same as `encode`. The items are written back to back
+    /// without an enclosing array, and can be read back one at a time with,
+    /// e.g., `Decoder::decode`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Encoder;
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// enc.encode_seq(vec![1, 500]).unwrap();
+    /// assert_eq!(enc.as_bytes(), &[0x01, 0x19, 0x01, 0xf4]);
+    /// ```
+    pub fn encode_seq<I>(&mut self, items: I) -> CborResult<()>
+        where I: IntoIterator, I::Item: Encodable {
+        self.encode(items)
+    }
+
+    /// Encode a single `Cbor` data item exactly as it is represented.
+    ///
+    /// Unlike encoding a `Cbor` value with `encode`, which goes through
+    /// `Encodable` and so always writes the smallest integer or float that
+    /// fits, this keeps the width of every integer and float. Tag numbers are
+    /// kept, `Undefined` is written as `undefined` (not `null`) and `Break` is
+    /// written as a break code.
+    ///
+    /// Map entries are written in canonical order (shorter keys first, then
+    /// byte-wise), since the order they were read in is not kept.
+    ///
+    /// This doesn't recurse, so values of any depth can be written without
+    /// overflowing the stack.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{Cbor, CborUnsigned, Encoder};
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// enc.encode_cbor(&Cbor::Unsigned(CborUnsigned::UInt32(1))).unwrap();
+    /// assert_eq!(enc.as_bytes(), &[0x1a, 0, 0, 0, 1]);
+    /// ```
+    pub fn encode_cbor(&mut self, v: &Cbor) -> CborResult<()> {
+        // Nested values are written from an explicit stack instead of by
+        // recursion, so that arbitrarily deep values can't overflow the
+        // call stack.
+        let mut stack = vec![Pending::Item(v)];
+        while let Some(next) = stack.pop() {
+            let v = match next {
+                Pending::Key(k) => { try!(self.emit_str(k)); continue; }
+                Pending::Item(v) => v,
+            };
+            match *v {
+                Cbor::Array(ref vals) => {
+                    try!(self.write_num(4, vals.len() as u64));
+                    stack.extend(vals.iter().rev().map(Pending::Item));
+                }
+                Cbor::Map(_) => {
+                    let entries = v.sort_keys();
+                    try!(self.write_num(5, entries.len() as u64));
+                    for (k, v) in entries.into_iter().rev() {
+                        stack.push(Pending::Item(v));
+                        stack.push(Pending::Key(k));
+                    }
+                }
+                Cbor::Tag(ref tag) => {
+                    try!(self.write_num(6, tag.tag));
+                    stack.push(Pending::Item(&tag.data));
+                }
+                _ => try!(self.encode_cbor_scalar(v)),
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes a `Cbor` value that isn't an array, map or tag for
+    /// `encode_cbor`.
+    fn encode_cbor_scalar(&mut self, v: &Cbor) -> CborResult<()> {
+        match *v {
+            Cbor::Break => self.write_all(&[0xff]),
+            Cbor::Undefined => self.write_all(&[0xf7]),
+            Cbor::Simple(v) => self.write_num(7, v as u64),
+            Cbor::Null => self.write_all(&[0xf6]),
+            Cbor::Bool(b) => {
+                self.write_all(&[if b { 0xf5 } else { 0xf4 }])
+            }
+            Cbor::Unsigned(n) => match n {
+                CborUnsigned::UInt8(n) => self.write_num(0, n as u64),
+                CborUnsigned::UInt16(n) => {
+                    self.write_num_fixed(0, 25, n as u64)
+                }
+                CborUnsigned::UInt32(n) => {
+                    self.write_num_fixed(0, 26, n as u64)
+                }
+                CborUnsigned::UInt64(n) => self.write_num_fixed(0, 27, n),
+            },
+            Cbor::Signed(n) => {
+                // The decoder widens a negative integer by one size when it
+                // doesn't fit in the signed type of its encoded width, so
+                // undo that here. Only values in that range were widened.
+                let (n, narrow, add) = match n {
+                    CborSigned::Int8(n) => (n as i64, (0, 0), 24),
+                    CborSigned::Int16(n) => {
+                        (n as i64, (i8::MAX as u64, u8::MAX as u64), 25)
+                    }
+                    CborSigned::Int32(n) => {
+                        (n as i64, (i16::MAX as u64, u16::MAX as u64), 26)
+                    }
+                    CborSigned::Int64(n) => {
+                        (n, (i32::MAX as u64, u32::MAX as u64), 27)
+                    }
+                };
+                let n = (-1 - n) as u64;
+                if add == 24 {
+                    self.write_num(1, n)
+                } else if n > narrow.0 && n <= narrow.1 {
+                    self.write_num_fixed(1, add - 1, n)
+                } else {
+                    self.write_num_fixed(1, add, n)
+                }
+            }
+            Cbor::Float(f) => {
+                try!(self.check_float(f.typ()));
+                match f {
+                    CborFloat::Float16(f) => {
+                        self.write_num_fixed(7, 25, half::from_f32(f) as u64)
+                    }
+                    CborFloat::Float32(f) => {
+                        self.write_num_fixed(7, 26, f.to_bits() as u64)
+                    }
+                    CborFloat::Float64(f) => {
+                        self.write_num_fixed(7, 27, f.to_bits())
+                    }
+                }
+            }
+            Cbor::Bytes(CborBytes(ref bytes)) => {
+                try!(self.write_num(2, bytes.len() as u64));
+                self.write_all(bytes)
+            }
+            Cbor::Unicode(ref s) => self.emit_str(s),
+            Cbor::Array(_) | Cbor::Map(_) | Cbor::Tag(_) => unreachable!(),
+        }
+    }
+
+    /// Write the header of an array with `len` elements.
+    ///
+    /// The elements should then be written as `len` data items, e.g., with
+    /// `encode`. The number of data items written afterwards is not checked,
+    /// so it is up to the caller to write exactly `len` of them.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Encoder;
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// enc.write_array_header(2).unwrap();
+    /// enc.encode(&["a", "b"]).unwrap();
+    /// assert_eq!(enc.as_bytes(), &[0x82, 0x61, 0x61, 0x61, 0x62]);
+    /// ```
+    pub fn write_array_header(&mut self, len: u64) -> CborResult<()> {
+        self.write_num(4, len)
+    }
+
+    /// Write the header of a map with `len` entries.
+    ///
+    /// Each entry should then be written as two data items: a Unicode string
+    /// key followed by its value. Like `write_array_header`, the number of
+    /// entries written afterwards is not checked.
+    pub fn write_map_header(&mut self, len: u64) -> CborResult<()> {
+        self.write_num(5, len)
+    }
+
+    /// Start writing an indefinite length byte string.
+    ///
+    /// The contents are written in chunks with `BytesStream::write_chunk`,
+    /// which is useful when the total length isn't known up front. The byte
+    /// string must be ended with `BytesStream::finish`, otherwise the encoded
+    /// data is incomplete.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Encoder;
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// {
+    ///     let mut stream = enc.bytes_stream().unwrap();
+    ///     stream.write_chunk(&[1, 2]).unwrap();
+    ///     stream.write_chunk(&[3]).unwrap();
+    ///     stream.finish().unwrap();
+    /// }
+    /// assert_eq!(enc.as_bytes(),
+    ///            &[0x5f, 0x42, 0x01, 0x02, 0x41, 0x03, 0xff]);
+    /// ```
+    pub fn bytes_stream(&mut self) -> CborResult<BytesStream<W>> {
+        try!(self.write_all(&[0x5f]));
+        Ok(BytesStream { enc: self })
+    }
+
     /// Flush the underlying writer.
     pub fn flush(&mut self) -> CborResult<()> {
         fromerr!(self.buf.flush())
     }
+
+    /// When enabled, encoding any floating point number returns an error.
+    ///
+    /// This is a guard for protocols that must never contain floats. It is
+    /// disabled by default.
+    pub fn set_forbid_floats(&mut self, yes: bool) {
+        self.forbid_floats = yes;
+    }
+
+    /// When enabled, enum variants without any fields are written as the
+    /// index of the variant (an unsigned integer) instead of its name.
+    ///
+    /// This makes C-like enums compact and interoperable with protocols that
+    /// use numeric codes. The decoder always accepts either form. It is
+    /// disabled by default.
+    pub fn set_unit_variants_as_index(&mut self, yes: bool) {
+        self.unit_variants_as_index = yes;
+    }
+
+    /// When enabled, enum variants with fields are written as a map with a
+    /// single entry from the name of the variant to its fields, e.g.,
+    /// `{"Blue": 5}` or `{"Rgb": [1, 2, 3]}`.
+    ///
+    /// A variant with one field maps to the value of the field, unless that
+    /// value is an array, in which case it is wrapped in an array (so that it
+    /// can't be mistaken for a list of fields). A variant with several fields
+    /// maps to an array of them. Variants without fields are written as just
+    /// their name (or index), as usual. This is a common convention in
+    /// JavaScript, and is more compact than the default
+    /// `{"variant": "Blue", "fields": [5]}`. The decoder always accepts
+    /// either form. It is disabled by default.
+    pub fn set_compact_enums(&mut self, yes: bool) {
+        self.compact_enums = yes;
+    }
+
+    /// When enabled, the entries of every map written with `emit_map` (e.g.,
+    /// a `HashMap`) are sorted, so that the output doesn't depend on
+    /// iteration order.
+    ///
+    /// Entries are ordered by their encoded keys: shorter keys come first,
+    /// and keys of the same length are compared byte-wise. If two entries
+    /// have identical encoded keys, they are ordered by their encoded values
+    /// in the same way. This is a total order, so the output is always
+    /// deterministic. Structs are not affected, since their fields are
+    /// always written in declaration order. It is disabled by default.
+    pub fn set_sort_map_keys(&mut self, yes: bool) {
+        self.sort_map_keys = yes;
+    }
+
+    /// When enabled, structs are written as arrays of their field values, in
+    /// declaration order, instead of maps from field names to values.
+    ///
+    /// This is more compact, but the order of fields becomes part of the
+    /// format. Every field always takes up its position (e.g., `None` is
+    /// written as `null`), so later fields never shift. Newtypes are still
+    /// written as their only field. The decoder accepts either form. It is
+    /// disabled by default.
+    pub fn set_structs_as_arrays(&mut self, yes: bool) {
+        self.structs_as_arrays = yes;
+    }
+
+    /// When set, every integer is written with an argument of exactly the
+    /// given width, even if it would fit in fewer bytes.
+    ///
+    /// This makes the size of records predictable. Encoding an integer that
+    /// doesn't fit in the width returns an error. Lengths and tag numbers
+    /// are not affected. This is `None` by default, which means the
+    /// shortest form is always used.
+    ///
+    /// Note that `Decoder` won't decode an integer into a Rust type narrower
+    /// than its encoded width, so, e.g., a `u8` written with
+    /// `IntWidth::Bits64` must be decoded as a `u64`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{Encoder, IntWidth};
+    ///
+    /// let mut enc = Encoder::from_memory();
+    /// enc.set_fixed_int_width(Some(IntWidth::Bits32));
+    /// enc.encode(&[1]).unwrap();
+    /// assert_eq!(enc.as_bytes(), &[0x1a, 0, 0, 0, 1]);
+    /// ```
+    pub fn set_fixed_int_width(&mut self, width: Option<IntWidth>) {
+        self.fixed_int_width = width;
+    }
+
+    /// Set the simple values that `false`, `true` and `null` (including
+    /// `None`) are written as.
+    ///
+    /// Encoding one of them returns an error if its simple value is
+    /// between 24 and 31, which aren't valid. `Cbor` values written with
+    /// `encode_cbor` are not affected. This is `SimpleValues::default()` by
+    /// default, i.e., the standard simple values.
+    pub fn set_simple_value_map(&mut self, map: SimpleValues) {
+        self.simple_values = map;
+    }
+}
+
+/// The width of the argument of an integer, for
+/// `Encoder::set_fixed_int_width`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum IntWidth {
+    /// One byte (additional information 24).
+    Bits8,
+    /// Two bytes (additional information 25).
+    Bits16,
+    /// Four bytes (additional information 26).
+    Bits32,
+    /// Eight bytes (additional information 27).
+    Bits64,
+}
+
+/// Something left to write by `Encoder::encode_cbor`.
+enum Pending<'a> {
+    Item(&'a Cbor),
+    Key(&'a str),
+}
+
+/// An indefinite length byte string being written, created by
+/// `Encoder::bytes_stream`.
+pub struct BytesStream<'a, W: 'a> {
+    enc: &'a mut Encoder<W>,
+}
+
+impl<'a, W: io::Write> BytesStream<'a, W> {
+    /// Write `bytes` as the next chunk of the byte string.
+    pub fn write_chunk(&mut self, bytes: &[u8]) -> CborResult<()> {
+        try!(self.enc.write_num(2, bytes.len() as u64));
+        self.enc.write_all(bytes)
+    }
+
+    /// End the byte string.
+    pub fn finish(self) -> CborResult<()> {
+        self.enc.write_all(&[0xff])
+    }
 }
 
 impl Encoder<Vec<u8>> {
@@ -127,6 +590,21 @@ impl Encoder<Vec<u8>> {
         self.flush().unwrap();
         self.buf
     }
+
+    /// Finish encoding and return the CBOR bytes that have been written.
+    ///
+    /// This is the same as `into_bytes`.
+    pub fn finish(self) -> Vec<u8> {
+        self.into_bytes()
+    }
+}
+
+impl<W: io::Write> Encoder<io::BufWriter<W>> {
+    /// Finish encoding, flush all buffered data and return the underlying
+    /// writer.
+    pub fn finish(self) -> CborResult<W> {
+        self.buf.into_inner().map_err(|err| CborError::Io(err.into()))
+    }
 }
 
 // /// Encodes a data item directly to CBOR bytes.
@@ -161,7 +639,8 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
 
     fn emit_nil(&mut self) -> CborResult<()> {
         no_string_key!(self, Type::Null);
-        fromerr!(self.buf.write_all(&[(7 << 5) | 22]))
+        let v = self.simple_values.null_value;
+        self.write_simple(v)
     }
 
     fn emit_usize(&mut self, v: usize) -> CborResult<()> {
@@ -171,7 +650,8 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
 
     fn emit_u64(&mut self, v: u64) -> CborResult<()> {
         no_string_key!(self, Type::UInt64);
-        if self.tag {
+        if self.tag_number {
+            self.tag_number = false;
             self.write_num(6, v)
         } else {
             self.write_uint(v)
@@ -191,7 +671,7 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
     fn emit_u8(&mut self, v: u8) -> CborResult<()> {
         no_string_key!(self, Type::UInt8);
         if self.byte_string {
-            fromerr!(self.buf.write_all(&[v]))
+            self.write_all(&[v])
         } else {
             self.write_uint(v as u64)
         }
@@ -224,22 +704,28 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
 
     fn emit_f64(&mut self, v: f64) -> CborResult<()> {
         no_string_key!(self, Type::Float64);
+        try!(self.check_float(Type::Float64));
         let mut buf = [(7 << 5) | 27, 0, 0, 0, 0, 0, 0, 0, 0];
         <BigEndian as ByteOrder>::write_f64(&mut buf[1..], v);
-        fromerr!(self.buf.write_all(&buf))
+        self.write_all(&buf)
     }
 
     fn emit_f32(&mut self, v: f32) -> CborResult<()> {
         no_string_key!(self, Type::Float32);
+        try!(self.check_float(Type::Float32));
         let mut buf = [(7 << 5) | 26, 0, 0, 0, 0];
         <BigEndian as ByteOrder>::write_f32(&mut buf[1..], v);
-        fromerr!(self.buf.write_all(&buf))
+        self.write_all(&buf)
     }
 
     fn emit_bool(&mut self, v: bool) -> CborResult<()> {
         no_string_key!(self, Type::Bool);
-        let n = if v { 21 } else { 20 };
-        fromerr!(self.buf.write_all(&[(7 << 5) | n]))
+        let n = if v {
+            self.simple_values.true_value
+        } else {
+            self.simple_values.false_value
+        };
+        self.write_simple(n)
     }
 
     fn emit_char(&mut self, v: char) -> CborResult<()> {
@@ -249,7 +735,7 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
 
     fn emit_str(&mut self, v: &str) -> CborResult<()> {
         try!(self.write_num(3, v.len() as u64));
-        fromerr!(self.buf.write_all(v.as_bytes()))
+        self.write_all(v.as_bytes())
     }
 
     fn emit_enum<F>(&mut self, _name: &str, f: F) -> CborResult<()>
@@ -260,15 +746,34 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
     fn emit_enum_variant<F>(
         &mut self,
         v_name: &str,
-        _v_id: usize,
+        v_id: usize,
         len: usize,
         f: F,
     ) -> CborResult<()>
     where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
         if len == 0 {
+            if self.unit_variants_as_index {
+                no_string_key!(self, Type::UInt);
+                // An index isn't an integer value, so it is never padded to
+                // a fixed width.
+                return self.write_num(0, v_id as u64);
+            }
             return self.emit_str(v_name);
         }
         no_string_key!(self);
+        if self.compact_enums {
+            try!(self.write_num(5, 1));
+            try!(self.emit_str(v_name));
+            if len == 1 {
+                let field = try!(self.capture(f));
+                if field.first().map_or(false, |&b| b >> 5 == 4) {
+                    try!(self.write_num(4, 1));
+                }
+                return self.write_all(&field);
+            }
+            try!(self.write_num(4, len as u64));
+            return f(self);
+        }
         try!(self.write_num(5, 2));
         try!(self.emit_str("variant"));
         try!(self.emit_str(v_name));
@@ -314,34 +819,54 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
     ) -> CborResult<()>
     where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
         no_string_key!(self, Type::Map);
-        match name {
-            "CborTag" => {
-                self.tag = true;
-                let v = f(self);
-                self.tag = false;
-                return v;
-            }
-            "CborTagEncode" => {
-                self.tag = true;
-                let v = f(self);
-                self.tag = false;
-                return v;
-            }
-            "CborBytes" => { self.byte_string = true; }
-            _ => { try!(self.write_num(5, len as u64)); }
-        }
-        f(self)
+        let positional = self.structs_as_arrays;
+        let is_tag = match name {
+            "CborTag" | "CborTagEncode" => true,
+            "CborBytes" => { self.byte_string = true; false }
+            // This might be a newtype, which we can't know until we see the
+            // name of its field. So hold off on writing the map header.
+            _ if len == 1 => { self.newtype = true; false }
+            _ if positional => { try!(self.write_num(4, len as u64)); false }
+            _ => { try!(self.write_num(5, len as u64)); false }
+        };
+        // Only the fields of the innermost struct being emitted are treated
+        // as tag fields, so that structs nested inside the tagged data item
+        // still get their field names written. The same goes for positional
+        // fields.
+        let (outer_tag, outer_positional) = (self.tag, self.positional);
+        self.tag = is_tag;
+        self.positional = positional;
+        let v = f(self);
+        self.tag = outer_tag;
+        self.positional = outer_positional;
+        self.newtype = false;
+        v
     }
 
     fn emit_struct_field<F>(
         &mut self,
         f_name: &str,
-        _f_idx: usize,
+        f_idx: usize,
         f: F,
     ) -> CborResult<()>
     where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
         no_string_key!(self);
-        if !self.byte_string && !self.is_tag_field(f_name) {
+        if self.tag {
+            // A tag is written as its number followed by its data item.
+            // Neither has a field name, and only the first field is the
+            // tag number.
+            self.tag_number = f_idx == 0;
+            return f(self);
+        }
+        if self.newtype {
+            self.newtype = false;
+            // Derived encoders name the fields of tuple structs `_fieldN`.
+            if f_name == "_field0" {
+                return f(self);
+            }
+            try!(self.write_num(if self.positional { 4 } else { 5 }, 1));
+        }
+        if !self.byte_string && !self.positional {
             try!(self.emit_str(f_name));
         }
         f(self)
@@ -416,21 +941,42 @@ impl<W: io::Write> RustcEncoder for Encoder<W> {
             where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
         no_string_key!(self, Type::Map);
         try!(self.write_num(5, len as u64));
-        f(self)
+        if !self.sort_map_keys {
+            return f(self);
+        }
+        self.sorted_maps.push(vec![]);
+        let r = f(self);
+        let mut entries = self.sorted_maps.pop().unwrap();
+        try!(r);
+        entries.sort_by(|&(ref k1, ref v1), &(ref k2, ref v2)| {
+            (k1.len(), k1, v1.len(), v1).cmp(&(k2.len(), k2, v2.len(), v2))
+        });
+        for (k, v) in entries {
+            try!(self.write_all(&k));
+            try!(self.write_all(&v));
+        }
+        Ok(())
     }
 
     fn emit_map_elt_key<F>(&mut self, _idx: usize, f: F) -> CborResult<()>
             where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
         no_string_key!(self);
-        self.emitting_key = true;
-        let r = f(self);
-        self.emitting_key = false;
-        r
+        if self.sort_map_keys {
+            let key = try!(self.capture(|e| e.emit_map_elt_key_raw(f)));
+            self.sorted_maps.last_mut().unwrap().push((key, vec![]));
+            return Ok(());
+        }
+        self.emit_map_elt_key_raw(f)
     }
 
     fn emit_map_elt_val<F>(&mut self, _idx: usize, f: F) -> CborResult<()>
             where F: FnOnce(&mut Encoder<W>) -> CborResult<()> {
         no_string_key!(self);
+        if self.sort_map_keys {
+            let val = try!(self.capture(f));
+            self.sorted_maps.last_mut().unwrap().last_mut().unwrap().1 = val;
+            return Ok(());
+        }
         f(self)
     }
 }
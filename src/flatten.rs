@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+
+use Cbor;
+
+impl Cbor {
+    /// Flatten this value into a map from the path of every leaf value to
+    /// the value itself.
+    ///
+    /// A path is the map keys and array indices leading to a leaf, joined by
+    /// dots, e.g., `user.names.0`. A leaf is any value that isn't an array or
+    /// map, or is an empty array or map (so that it isn't lost). Tags are
+    /// leaves too. If this value is a leaf itself, its path is the empty
+    /// string.
+    ///
+    /// A dot or backslash in a map key is escaped with a backslash, so the
+    /// key `a.b` is written as `a\.b`. An empty key at the top level has the
+    /// same path as the root, and so doesn't survive a round trip through
+    /// `unflatten`.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use cbor::Value;
+    ///
+    /// let mut user = HashMap::new();
+    /// user.insert("names".to_owned(), Value::from(vec![Value::from("a")]));
+    /// let mut doc = HashMap::new();
+    /// doc.insert("user".to_owned(), Value::from(user));
+    /// let doc = Value::from(doc);
+    ///
+    /// let flat = doc.flatten();
+    /// assert_eq!(flat["user.names.0"], Value::from("a"));
+    /// assert_eq!(Value::unflatten(flat), doc);
+    /// ```
+    pub fn flatten(&self) -> HashMap<String, Cbor> {
+        let mut flat = HashMap::new();
+        flatten_into(&mut String::new(), self, &mut flat);
+        flat
+    }
+
+    /// Rebuild a value from a map of paths to leaf values, as produced by
+    /// `flatten`.
+    ///
+    /// Paths use the same syntax (and escapes) as `flatten`. The maps along
+    /// each path are created as needed. A map whose keys are exactly the
+    /// indices `0` to `n - 1` becomes an array, so a map with only such keys
+    /// comes back as an array. If a leaf and a longer path conflict (e.g.,
+    /// `a` and `a.b`), the longer path wins.
+    pub fn unflatten(flat: HashMap<String, Cbor>) -> Cbor {
+        // Sorted so that the result doesn't depend on iteration order.
+        let mut flat: Vec<_> = flat.into_iter().collect();
+        flat.sort_by(|a, b| a.0.cmp(&b.0));
+        let mut root = Node::Branch(HashMap::new());
+        for (path, v) in flat {
+            let segs = parse_path(&path);
+            root.insert(&segs, v);
+        }
+        root.into_cbor()
+    }
+}
+
+fn flatten_into(
+    path: &mut String,
+    v: &Cbor,
+    flat: &mut HashMap<String, Cbor>,
+) {
+    let len = path.len();
+    match *v {
+        Cbor::Array(ref vals) if !vals.is_empty() => {
+            for (i, v) in vals.iter().enumerate() {
+                push_segment(path, len, &i.to_string(), false);
+                flatten_into(path, v, flat);
+                path.truncate(len);
+            }
+        }
+        Cbor::Map(ref map) if !map.is_empty() => {
+            for (k, v) in map {
+                push_segment(path, len, k, true);
+                flatten_into(path, v, flat);
+                path.truncate(len);
+            }
+        }
+        _ => { flat.insert(path.clone(), v.clone()); }
+    }
+}
+
+fn push_segment(path: &mut String, len: usize, seg: &str, escape: bool) {
+    if len > 0 {
+        path.push('.');
+    }
+    for c in seg.chars() {
+        if escape && (c == '.' || c == '\\') {
+            path.push('\\');
+        }
+        path.push(c);
+    }
+}
+
+fn parse_path(path: &str) -> Vec<String> {
+    if path.is_empty() {
+        return vec![];
+    }
+    let mut segs = vec![String::new()];
+    let mut chars = path.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(c) => segs.last_mut().unwrap().push(c),
+                None => segs.last_mut().unwrap().push('\\'),
+            },
+            '.' => segs.push(String::new()),
+            c => segs.last_mut().unwrap().push(c),
+        }
+    }
+    segs
+}
+
+enum Node {
+    Leaf(Cbor),
+    Branch(HashMap<String, Node>),
+}
+
+impl Node {
+    fn insert(&mut self, segs: &[String], v: Cbor) {
+        if segs.is_empty() {
+            // A branch that is still empty was only just created for this
+            // leaf. Otherwise, a longer path already went through here.
+            let fresh = match *self {
+                Node::Leaf(_) => true,
+                Node::Branch(ref map) => map.is_empty(),
+            };
+            if fresh {
+                *self = Node::Leaf(v);
+            }
+            return;
+        }
+        if let Node::Leaf(_) = *self {
+            *self = Node::Branch(HashMap::new());
+        }
+        if let Node::Branch(ref mut map) = *self {
+            map.entry(segs[0].clone())
+               .or_insert_with(|| Node::Branch(HashMap::new()))
+               .insert(&segs[1..], v);
+        }
+    }
+
+    fn into_cbor(self) -> Cbor {
+        let mut map = match self {
+            Node::Leaf(v) => return v,
+            Node::Branch(map) => map,
+        };
+        let is_array = !map.is_empty()
+            && (0..map.len()).all(|i| map.contains_key(&i.to_string()));
+        if is_array {
+            Cbor::Array((0..map.len())
+                .map(|i| map.remove(&i.to_string()).unwrap().into_cbor())
+                .collect())
+        } else {
+            Cbor::Map(map.into_iter().map(|(k, v)| (k, v.into_cbor()))
+                                     .collect())
+        }
+    }
+}
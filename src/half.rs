@@ -0,0 +1,49 @@
+//! Conversions between IEEE 754 half-precision floats and `f32`.
+//!
+//! Rust has no `f16` type, so half-precision floats are carried around as
+//! `f32` values in memory and as raw `u16` bits on the wire.
+
+/// Converts the bits of a half-precision float to an `f32`.
+///
+/// Every half-precision value has an exact `f32` representation, so this is
+/// lossless (except for NaN payloads).
+pub fn to_f32(h: u16) -> f32 {
+    let exp = ((h >> 10) & 0x1f) as i32;
+    let mant = (h & 0x3ff) as f32;
+    let val = match exp {
+        0 => mant * 2f32.powi(-24),
+        31 if mant == 0.0 => ::std::f32::INFINITY,
+        31 => ::std::f32::NAN,
+        _ => (1.0 + mant / 1024.0) * 2f32.powi(exp - 15),
+    };
+    if h & 0x8000 != 0 { -val } else { val }
+}
+
+/// Converts an `f32` to the bits of a half-precision float.
+///
+/// Values that can't be represented exactly are truncated towards zero, and
+/// values that are too large become infinity.
+pub fn from_f32(v: f32) -> u16 {
+    let bits = v.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exp = ((bits >> 23) & 0xff) as i32;
+    let mant = bits & 0x7fffff;
+    if exp == 0xff {
+        let nan = if mant != 0 { 0x200 } else { 0 };
+        return sign | 0x7c00 | nan;
+    }
+    let e = exp - 127 + 15;
+    if e >= 31 {
+        sign | 0x7c00
+    } else if e <= 0 {
+        // A subnormal, or zero if it is too small.
+        let shift = 14 - e;
+        if shift > 24 {
+            sign
+        } else {
+            sign | ((mant | 0x800000) >> shift) as u16
+        }
+    } else {
+        sign | (e as u16) << 10 | (mant >> 13) as u16
+    }
+}
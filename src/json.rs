@@ -1,7 +1,10 @@
 use rustc_serialize::base64::{STANDARD, ToBase64};
 use rustc_serialize::json::{Json, ToJson};
 
-use {Cbor, CborFloat, CborSigned, CborUnsigned};
+use {
+    Cbor, CborError, CborFloat, CborResult, CborSigned, CborUnsigned, Decoder,
+    Encoder, ReadError,
+};
 
 /// A trait for converting values to CBOR.
 pub trait ToCbor {
@@ -9,11 +12,70 @@ pub trait ToCbor {
     fn to_cbor(&self) -> Cbor;
 }
 
+/// Parse a JSON document and encode it as a single CBOR data item.
+///
+/// JSON objects become maps, and numbers become unsigned integers, negative
+/// integers or floats, depending on how they are written. The output is
+/// canonical (see `Cbor::canonicalize`), so numbers are written in their
+/// narrowest width and map keys are sorted. An error is returned if `json`
+/// isn't valid JSON.
+///
+/// # Example
+///
+/// ```rust
+/// let bytes = cbor::json_to_cbor_bytes(r#"[1, "a"]"#).unwrap();
+/// assert_eq!(bytes, vec![0x82, 0x01, 0x61, 0x61]);
+/// ```
+pub fn json_to_cbor_bytes(json: &str) -> CborResult<Vec<u8>> {
+    let json = try!(Json::from_str(json).map_err(|err| {
+        CborError::Decode(ReadError::Other(format!("Invalid JSON: {}", err)))
+    }));
+    let mut v = json.to_cbor();
+    v.canonicalize();
+    let mut enc = Encoder::from_memory();
+    try!(enc.encode_cbor(&v));
+    Ok(enc.into_bytes())
+}
+
+/// Decode a single CBOR data item and write it as a JSON document.
+///
+/// This uses the same conversion as `Cbor`'s `ToJson` impl, which is lossy
+/// for data items that JSON can't represent: byte strings become base64
+/// strings (with padding), tags are dropped in favor of their data items,
+/// undefined becomes `null`, and NaN and infinities become `null` too.
+/// Integer and float widths aren't kept either. An error is returned if
+/// `bytes` doesn't contain exactly one data item.
+///
+/// # Example
+///
+/// ```rust
+/// // {"a": h'0102'}
+/// let bytes = vec![0xa1, 0x61, 0x61, 0x42, 0x01, 0x02];
+/// assert_eq!(cbor::cbor_bytes_to_json(&bytes).unwrap(), r#"{"a":"AQI="}"#);
+/// ```
+pub fn cbor_bytes_to_json(bytes: &[u8]) -> CborResult<String> {
+    let mut dec = Decoder::from_bytes(bytes);
+    let mut items = dec.items();
+    let v = match items.next() {
+        Some(Ok(Cbor::Break)) => return Err(CborError::Decode(
+            ReadError::Other("Found break outside of an indefinite length \
+                              data item.".to_owned()))),
+        Some(v) => try!(v),
+        None => return Err(CborError::UnexpectedEOF),
+    };
+    if items.next().is_some() {
+        return Err(CborError::Decode(ReadError::Other(
+            "Found trailing data after the data item.".to_owned())));
+    }
+    Ok(v.to_json().to_string())
+}
+
 impl ToJson for Cbor {
     fn to_json(&self) -> Json {
         match *self {
             Cbor::Break => unimplemented!(),
             Cbor::Undefined => Json::Null,
+            Cbor::Simple(_) => Json::Null,
             Cbor::Null => Json::Null,
             Cbor::Bool(v) => Json::Boolean(v),
             Cbor::Unsigned(v) => Json::U64(v.to_u64().unwrap()),
@@ -6,12 +6,13 @@ model* used by JSON, except the encoding is in binary form. Its primary goals
 include a balance of implementation size, message size and extensibility.
 
 The implementation here is mostly complete. It includes a mechanism for
-serializing and deserializing your own tags, but it does not yet support
-indefinite length encoding.
+serializing and deserializing your own tags. Indefinite length data items can
+be decoded, but they are never produced by the encoder.
 
 This library is primarily used with type-based encoding and decoding via the
 `rustc-serialize` infrastructure, but the raw CBOR abstract syntax is exposed
-for use cases that call for it.
+for use cases that call for it. The `Value` type is the way to work with data
+whose structure isn't known ahead of time.
 
 # Example: simple type based encoding and decoding
 
@@ -112,6 +113,14 @@ assert_eq!(cbor.to_json(), Json::Array(vec![
 
 This crate also defines a `ToCbor` trait and implements it for the `Json` type,
 so you can convert JSON to CBOR in a similar manner as above.
+
+# Wrapper types
+
+`Encodable` and `Decodable` can't be implemented here for types defined in
+other crates (including the standard library), since the traits are defined
+in `rustc-serialize`. Instead, such types are supported through wrappers like
+`CborAtomicU64` or `CborBinaryHeap`, which hold the wrapped value as their
+only (public) field.
 */
 #![crate_name = "cbor"]
 #![doc(html_root_url = "http://burntsushi.net/rustdoc/cbor")]
@@ -119,19 +128,49 @@ so you can convert JSON to CBOR in a similar manner as above.
 
 extern crate byteorder;
 extern crate rustc_serialize;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "quickcheck")]
+extern crate quickcheck;
+#[cfg(feature = "uuid")]
+extern crate uuid;
 
 use std::collections::HashMap;
 use std::fmt;
 use std::io;
+use std::mem;
 
 use rustc_serialize::Decoder as RustcDecoder;
 use rustc_serialize::Encoder as RustcEncoder;
 use rustc_serialize::{Decodable, Encodable};
 
-pub use decoder::Decoder;
-pub use encoder::Encoder;
-pub use json::ToCbor;
+use rustc_decoder::CborDecoder;
+
+pub use atomic::{
+    CborAtomicI16, CborAtomicI32, CborAtomicI64, CborAtomicI8, CborAtomicIsize,
+    CborAtomicU16, CborAtomicU32, CborAtomicU64, CborAtomicU8, CborAtomicUsize,
+};
+pub use binary_heap::CborBinaryHeap;
+pub use canonical::bytes_eq_canonical;
+pub use control_flow::CborControlFlow;
+pub use debug::debug_dump;
+pub use decoder::{DateTimeMode, Decoder, TupleFields};
+pub use diff::{Change, PathSegment, diff};
+pub use encoder::{BytesStream, Encoder, IntWidth};
+pub use json::{ToCbor, cbor_bytes_to_json, json_to_cbor_bytes};
 pub use rustc_decoder_direct::CborDecoder as DirectDecoder;
+#[cfg(feature = "bytes")]
+pub use shared_bytes::CborSharedBytes;
+pub use tags::{
+    CborBitVec, CborMime, CborRegex, CborTime, CborUuid, read_tagged,
+};
+#[cfg(feature = "chrono")]
+pub use tags::{CborDateTime, CborEpochDateTime};
+pub use weak::{CborArcWeak, CborRcWeak};
+pub use value::DiagOptions;
+pub use wellformed::split_sequence;
 
 // A trivial logging macro. No reason to pull in `log`, which has become
 // difficult to use in tests.
@@ -156,7 +195,7 @@ pub enum Type {
     Int, Int8, Int16, Int32, Int64,
     Float, Float16, Float32, Float64,
     Bytes, Unicode, Array, Map, Tag,
-    Any, Null, Undefined, Bool, Break,
+    Any, Null, Undefined, Bool, Break, Simple,
 }
 
 impl fmt::Display for Type {
@@ -215,6 +254,7 @@ impl Type {
             Type::Tag => 6,
             Type::Float | Type::Float16 | Type::Float32 | Type::Float64 => 7,
             Type::Null | Type::Undefined | Type::Bool | Type::Break => 7,
+            Type::Simple => 7,
             Type::Any => unreachable!(),
         }
     }
@@ -233,6 +273,9 @@ pub enum Cbor {
     Break, // does this really belong here?
     /// An undefined data item (major type 7, value 23).
     Undefined,
+    /// A simple value without an assigned meaning (major type 7, values 0 to
+    /// 19 and 32 to 255).
+    Simple(u8),
     /// A null data item (major type 7, value 22).
     Null,
     /// A boolean data item (major type 7, values 20 or 21).
@@ -250,11 +293,88 @@ pub enum Cbor {
     /// An array (major type 4).
     Array(Vec<Cbor>),
     /// A map (major type 5).
+    ///
+    /// Entries aren't kept in any order, but `Cbor::sort_keys` returns them
+    /// in canonical order. `Encoder::encode_cbor` and `Display` always write
+    /// entries in a deterministic order (see `Encoder::set_sort_map_keys` for
+    /// values encoded with `encode`).
     Map(HashMap<String, Cbor>),
     /// A tag (major type 6).
     Tag(CborTag),
 }
 
+/// A dynamically typed CBOR value.
+///
+/// This is the same type as `Cbor`, and is the recommended name to use for
+/// data whose structure isn't known ahead of time. It can be built with
+/// `From`, taken apart with the `as_*` accessors, `get` or indexing (which
+/// yields `Value::Null` for missing entries), converted with `TryFrom` and
+/// printed in diagnostic notation with `Display`.
+///
+/// # Example
+///
+/// ```rust
+/// use std::collections::HashMap;
+/// use cbor::{Decoder, Encoder, Value};
+///
+/// let mut map = HashMap::new();
+/// map.insert("name".to_owned(), Value::from("cbor"));
+/// map.insert("tags".to_owned(), Value::from(vec![Value::from(-1),
+///                                                Value::from(true)]));
+/// let value = Value::from(map);
+/// assert_eq!(value["name"].as_str(), Some("cbor"));
+/// assert_eq!(value["tags"][0].as_i64(), Some(-1));
+/// assert!(value["missing"][3].is_null());
+/// assert_eq!(value.to_string(), r#"{"name": "cbor", "tags": [-1, true]}"#);
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[&value]).unwrap();
+/// let mut dec = Decoder::from_bytes(enc.as_bytes());
+/// let decoded: Value = dec.items().next().unwrap().unwrap();
+/// assert_eq!(decoded["tags"][1].as_bool(), Some(true));
+/// ```
+pub type Value = Cbor;
+
+/// A single event produced by the streaming event decoder.
+///
+/// See `Decoder::events`. Arrays, maps and tags are announced by a start
+/// event and are followed by the events of their contents, so no nested
+/// `Cbor` values are ever built. Strings, including indefinite length
+/// strings, are produced whole.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Event {
+    /// An unsigned integer (major type 0).
+    Unsigned(CborUnsigned),
+    /// A negative integer (major type 1).
+    Signed(CborSigned),
+    /// An IEEE 754 floating point number (major type 7).
+    Float(CborFloat),
+    /// A boolean (major type 7, values 20 or 21).
+    Bool(bool),
+    /// A null (major type 7, value 22).
+    Null,
+    /// An undefined value (major type 7, value 23).
+    Undefined,
+    /// An unassigned simple value (major type 7).
+    Simple(u8),
+    /// A byte string (major type 2).
+    Bytes(CborBytes),
+    /// A Unicode string (major type 3).
+    Unicode(String),
+    /// The start of an array with the given number of elements, or `None`
+    /// if it has an indefinite length (in which case a `Break` event ends
+    /// it).
+    ArrayStart(Option<usize>),
+    /// The start of a map with the given number of entries, or `None` if it
+    /// has an indefinite length (in which case a `Break` event ends it).
+    MapStart(Option<usize>),
+    /// A tag with the given number. The events of the data item that it
+    /// tags come next. Each tag in a chain of tags is its own event.
+    Tag(u64),
+    /// The end of an indefinite length array or map.
+    Break,
+}
+
 /// An unsigned integer (major type 0).
 #[derive(Clone, Copy, Debug, Eq, Ord, PartialEq, PartialOrd, RustcDecodable)]
 pub enum CborUnsigned {
@@ -291,7 +411,7 @@ pub enum CborSigned {
 pub enum CborFloat {
     /// IEEE 754 half-precision float.
     ///
-    /// *WARNING*: This may be broken right now. ---AG
+    /// Since Rust has no `f16` type, the value is stored as an `f32`.
     Float16(f32),
     /// IEEE 754 single-precision float.
     Float32(f32),
@@ -299,6 +419,14 @@ pub enum CborFloat {
     Float64(f64),
 }
 
+/// An integer of either sign (major type 0 or 1).
+///
+/// This can represent every unsigned and negative integer in CBOR, so it is
+/// a convenient way to get at the value of `Cbor::Unsigned` and `Cbor::Signed`
+/// alike. See `Cbor::as_integer`.
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CborInteger(pub i128);
+
 /// A byte string (major type 2).
 #[derive(Clone, Debug, Eq, Ord, PartialEq, PartialOrd, RustcEncodable)]
 pub struct CborBytes(pub Vec<u8>);
@@ -324,6 +452,40 @@ pub struct CborTag {
     pub data: Box<Cbor>,
 }
 
+impl CborTag {
+    /// Create a new tag with tag number `tag` over the data item `data`.
+    pub fn new<T: Into<Cbor>>(tag: u64, data: T) -> CborTag {
+        CborTag { tag: tag, data: Box::new(data.into()) }
+    }
+
+    /// Decode the tagged data item into a Rust value.
+    ///
+    /// This is the decoding counterpart of `CborTagEncode`: the tag number
+    /// is left out, and only the data item is decoded.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{CborTag, Decoder, Encoder, Value};
+    ///
+    /// let tag = CborTag::new(100_000, vec![Value::from(1), Value::from(2)]);
+    /// let mut enc = Encoder::from_memory();
+    /// enc.encode(&[Value::from(tag)]).unwrap();
+    ///
+    /// let mut dec = Decoder::from_bytes(enc.as_bytes());
+    /// match dec.items().next().unwrap().unwrap() {
+    ///     Value::Tag(tag) => {
+    ///         assert_eq!(tag.tag, 100_000);
+    ///         assert_eq!(tag.decode::<Vec<u32>>().unwrap(), vec![1, 2]);
+    ///     }
+    ///     v => panic!("expected a tag, got {:?}", v),
+    /// }
+    /// ```
+    pub fn decode<D: Decodable>(&self) -> CborResult<D> {
+        Decodable::decode(&mut CborDecoder::new((*self.data).clone()))
+    }
+}
+
 /// A special type that can be used to encode CBOR tags.
 ///
 /// This is a "special" type because its used is hard-coded into the
@@ -405,11 +567,203 @@ impl<'a, T> CborTagEncode<'a, T> {
     }
 }
 
+/// The simple values (major type 7) that stand for `false`, `true` and
+/// `null`.
+///
+/// By default, these are the standard simple values 20, 21 and 22. Some
+/// protocols repurpose other simple values instead, which can be configured
+/// with `Encoder::set_simple_value_map` and `Decoder::set_simple_value_map`.
+/// A simple value must be between 0 and 23 or between 32 and 255.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SimpleValues {
+    /// The simple value for `false`.
+    pub false_value: u8,
+    /// The simple value for `true`.
+    pub true_value: u8,
+    /// The simple value for `null` (which is also used for `None`).
+    pub null_value: u8,
+}
+
+impl Default for SimpleValues {
+    fn default() -> SimpleValues {
+        SimpleValues { false_value: 20, true_value: 21, null_value: 22 }
+    }
+}
+
 impl Cbor {
+    /// Retain only the elements of an array for which `f` returns `true`.
+    ///
+    /// This is not recursive: only the immediate elements of this array are
+    /// passed to `f`, and nested arrays are left untouched. To prune nested
+    /// data, call `retain` on each element yourself.
+    ///
+    /// If this value is not an array, then this does nothing.
+    pub fn retain<F>(&mut self, f: F) where F: FnMut(&Cbor) -> bool {
+        if let Cbor::Array(ref mut vals) = *self {
+            vals.retain(f);
+        }
+    }
+
+    /// Retain only the entries of a map for which `f` returns `true`.
+    ///
+    /// `f` is given the key and value of each entry. Like `retain`, this is
+    /// not recursive: only the immediate entries of this map are visited.
+    ///
+    /// If this value is not a map, then this does nothing.
+    pub fn retain_entries<F>(&mut self, mut f: F)
+            where F: FnMut(&str, &Cbor) -> bool {
+        if let Cbor::Map(ref mut map) = *self {
+            map.retain(|k, v| f(k, v));
+        }
+    }
+
+    /// Rewrite this value, and every value nested inside it, into canonical
+    /// form.
+    ///
+    /// Integers use the narrowest width that can hold them, and floats are
+    /// narrowed to the smallest width that represents them exactly (NaN
+    /// always becomes a half-precision NaN). Two values that differ only in
+    /// these widths are equal after being canonicalized.
+    ///
+    /// Lengths are always definite in memory, and map keys are sorted when
+    /// a map is written with `Encoder::encode_cbor`, so encoding a
+    /// canonicalized value always produces the same bytes.
+    pub fn canonicalize(&mut self) {
+        match *self {
+            Cbor::Unsigned(ref mut v) => {
+                let n = v.to_u64().unwrap();
+                *v = if n <= ::std::u8::MAX as u64 {
+                    CborUnsigned::UInt8(n as u8)
+                } else if n <= ::std::u16::MAX as u64 {
+                    CborUnsigned::UInt16(n as u16)
+                } else if n <= ::std::u32::MAX as u64 {
+                    CborUnsigned::UInt32(n as u32)
+                } else {
+                    CborUnsigned::UInt64(n)
+                };
+            }
+            Cbor::Signed(ref mut v) => {
+                let n = v.to_i64().unwrap();
+                *v = if n >= ::std::i8::MIN as i64 {
+                    CborSigned::Int8(n as i8)
+                } else if n >= ::std::i16::MIN as i64 {
+                    CborSigned::Int16(n as i16)
+                } else if n >= ::std::i32::MIN as i64 {
+                    CborSigned::Int32(n as i32)
+                } else {
+                    CborSigned::Int64(n)
+                };
+            }
+            Cbor::Float(ref mut v) => {
+                let f = v.to_f64().unwrap();
+                let single = f as f32;
+                *v = if f.is_nan() {
+                    CborFloat::Float16(::std::f32::NAN)
+                } else if half::to_f32(half::from_f32(single)) as f64 == f {
+                    CborFloat::Float16(single)
+                } else if single as f64 == f {
+                    CborFloat::Float32(single)
+                } else {
+                    CborFloat::Float64(f)
+                };
+            }
+            Cbor::Array(ref mut vals) => {
+                for v in vals {
+                    v.canonicalize();
+                }
+            }
+            Cbor::Map(ref mut map) => {
+                for v in map.values_mut() {
+                    v.canonicalize();
+                }
+            }
+            Cbor::Tag(ref mut tag) => tag.data.canonicalize(),
+            _ => {}
+        }
+    }
+
+    /// Returns the entries of this map in canonical key order (shorter keys
+    /// first, then byte-wise), without touching any integer or float widths.
+    ///
+    /// A `Cbor::Map` is a `HashMap`, so it can't be sorted in place. This is
+    /// the order that `Encoder::encode_cbor` writes entries in. Nested maps
+    /// can be sorted by calling `sort_keys` on the values in turn. If this
+    /// value isn't a map, then the result is empty.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use cbor::Cbor;
+    ///
+    /// let mut map = HashMap::new();
+    /// for k in &["bb", "c", "a"] {
+    ///     map.insert(k.to_string(), Cbor::Null);
+    /// }
+    /// let map = Cbor::Map(map);
+    /// let keys: Vec<&str> = map.sort_keys().into_iter()
+    ///                          .map(|(k, _)| &k[..])
+    ///                          .collect();
+    /// assert_eq!(keys, vec!["a", "c", "bb"]);
+    /// ```
+    pub fn sort_keys(&self) -> Vec<(&String, &Cbor)> {
+        let mut entries: Vec<_> = match *self {
+            Cbor::Map(ref map) => map.iter().collect(),
+            _ => return vec![],
+        };
+        entries.sort_by(|&(k1, _), &(k2, _)| {
+            (k1.len(), k1).cmp(&(k2.len(), k2))
+        });
+        entries
+    }
+
+    /// Returns the value of this data item if it is an integer of either
+    /// sign, and `None` otherwise.
+    pub fn as_integer(&self) -> Option<CborInteger> {
+        match *self {
+            Cbor::Unsigned(v) => v.to_u64().ok().map(|v| v as i128),
+            Cbor::Signed(v) => v.to_i64().ok().map(|v| v as i128),
+            _ => None,
+        }.map(CborInteger)
+    }
+
+    /// Returns an estimate of the total number of bytes occupied by this
+    /// value, including every heap allocation it owns.
+    ///
+    /// This counts the capacity of strings, byte strings, arrays and maps,
+    /// and recurses into nested values. Map bookkeeping is approximated, so
+    /// the result is best used for budgeting rather than exact accounting.
+    pub fn deep_size(&self) -> usize {
+        mem::size_of::<Cbor>() + self.heap_size()
+    }
+
+    fn heap_size(&self) -> usize {
+        match *self {
+            Cbor::Bytes(CborBytes(ref bytes)) => bytes.capacity(),
+            Cbor::Unicode(ref s) => s.capacity(),
+            Cbor::Array(ref vals) => {
+                let spare = vals.capacity() - vals.len();
+                spare * mem::size_of::<Cbor>()
+                + vals.iter().map(Cbor::deep_size).sum::<usize>()
+            }
+            Cbor::Map(ref map) => {
+                // Each bucket holds a key and a value, plus a control byte.
+                let bucket = mem::size_of::<(String, Cbor)>() + 1;
+                map.capacity() * bucket
+                + map.iter()
+                     .map(|(k, v)| k.capacity() + v.heap_size())
+                     .sum::<usize>()
+            }
+            Cbor::Tag(ref tag) => tag.data.deep_size(),
+            _ => 0,
+        }
+    }
+
     fn typ(&self) -> Type {
         match *self {
             Cbor::Break => Type::Break,
             Cbor::Undefined => Type::Undefined,
+            Cbor::Simple(_) => Type::Simple,
             Cbor::Null => Type::Null,
             Cbor::Bool(_) => Type::Bool,
             Cbor::Unsigned(v) => v.typ(),
@@ -435,14 +789,14 @@ impl CborUnsigned {
     }
 
     fn to_usize(self) -> ReadResult<usize> {
-        // It should be possible for this to fail. e.g., Converting a
-        // UInt64 to a usize when usize is 32 bits.
-        Ok(match self {
-            CborUnsigned::UInt8(v) => v as usize,
-            CborUnsigned::UInt16(v) => v as usize,
-            CborUnsigned::UInt32(v) => v as usize,
-            CborUnsigned::UInt64(v) => v as usize,
-        })
+        // This fails when, e.g., converting a UInt64 to a usize when usize
+        // is 32 bits.
+        let v = try!(self.to_u64());
+        if v > ::std::usize::MAX as u64 {
+            return Err(ReadError::Other(format!(
+                "Unsigned integer {} does not fit in a usize.", v)));
+        }
+        Ok(v as usize)
     }
 
     fn to_u64(self) -> ReadResult<u64> {
@@ -492,14 +846,14 @@ impl CborSigned {
     }
 
     fn to_isize(self) -> ReadResult<isize> {
-        // It should be possible for this to fail. e.g., Converting a
-        // UInt64 to a usize when usize is 32 bits.
-        Ok(match self {
-            CborSigned::Int8(v) => v as isize,
-            CborSigned::Int16(v) => v as isize,
-            CborSigned::Int32(v) => v as isize,
-            CborSigned::Int64(v) => v as isize,
-        })
+        // This fails when, e.g., converting an Int64 to an isize when isize
+        // is 32 bits.
+        let v = try!(self.to_i64());
+        if v < ::std::isize::MIN as i64 {
+            return Err(ReadError::Other(format!(
+                "Negative integer {} does not fit in an isize.", v)));
+        }
+        Ok(v as isize)
     }
 
     fn to_i64(self) -> ReadResult<i64> {
@@ -566,14 +920,29 @@ impl CborFloat {
     }
 }
 
+/// Encodes a `Cbor` value with any `rustc-serialize` encoder.
+///
+/// This can't keep the stored widths, since `Encodable` only describes
+/// values, not how they are written. Every integer and float is written by
+/// the encoder like any other Rust number (with this crate's `Encoder`, in
+/// the smallest width that holds it), `Undefined` and `Simple` values are
+/// written as null and map entries are written in iteration order unless
+/// `Encoder::set_sort_map_keys` is enabled. Use `Encoder::encode_cbor` to
+/// write a value exactly as it is represented.
+///
+/// # Panics
+///
+/// `Cbor::Break` isn't a data item on its own, and there is no way to return
+/// an error from a generic encoder, so encoding it panics. `encode_cbor`
+/// writes it as a break code instead.
 impl Encodable for Cbor {
     fn encode<E: RustcEncoder>(&self, e: &mut E) -> Result<(), E::Error> {
         match *self {
-            // Not sure what to do with `Break` here. I guess if we need to
-            // be able to encode, we'll have to add special support for it
-            // in the encoder.
-            Cbor::Break => unimplemented!(),
+            Cbor::Break => panic!(
+                "Cbor::Break can't be encoded with Encodable. Use \
+                 Encoder::encode_cbor to write a break code."),
             Cbor::Undefined => e.emit_nil(),
+            Cbor::Simple(_) => e.emit_nil(),
             Cbor::Null => e.emit_nil(),
             Cbor::Bool(v) => v.encode(e),
             Cbor::Unsigned(v) => v.encode(e),
@@ -719,6 +1088,8 @@ pub enum WriteError {
         /// The received type (if that information is available).
         got: Option<Type>,
     },
+    /// Some other error occurred.
+    Other(String),
 }
 
 impl From<io::Error> for CborError {
@@ -803,12 +1174,30 @@ impl fmt::Display for WriteError {
             WriteError::InvalidMapKey { got: None } => {
                 write!(f, "Found invalid map key, expected Unicode string.")
             }
+            WriteError::Other(ref s) => write!(f, "{}", s),
         }
     }
 }
 
+#[cfg(feature = "quickcheck")]
+mod arbitrary;
+mod atomic;
+mod binary_heap;
+mod canonical;
+mod control_flow;
+mod datetime;
+mod debug;
 mod decoder;
+mod diff;
 mod encoder;
+mod flatten;
+mod half;
 mod json;
 mod rustc_decoder;
 mod rustc_decoder_direct;
+#[cfg(feature = "bytes")]
+mod shared_bytes;
+mod tags;
+mod value;
+mod weak;
+mod wellformed;
@@ -5,17 +5,76 @@ use rustc_serialize::Decoder as RustcDecoder;
 
 use {Cbor, CborUnsigned, Type, CborResult, CborError, ReadError};
 
+/// The variant names `CborTime` reads the content of tag 1 with.
+///
+/// `read_enum_variant` doesn't consume anything for these names. The variant
+/// index is chosen by the type of the next data item (a negative integer, an
+/// unsigned integer or anything else), which is then read as the only
+/// argument. This keeps integer epoch times exact.
+pub const EPOCH_VARIANTS: &'static [&'static str] =
+    &["__cbor_epoch_signed", "__cbor_epoch_unsigned", "__cbor_epoch_float"];
+
 pub struct CborDecoder {
     stack: Vec<Cbor>,
+    // One entry for each struct currently being decoded.
+    structs: Vec<StructRepr>,
+    // Whether a map can also be decoded from an array of pairs.
+    accept_array_as_map: bool,
+    // Whether a newtype can also be decoded from a `{"_field0": ...}` map.
+    accept_field0_maps: bool,
+    // Whether only floats (and not integers) can be decoded as floats.
+    strict_floats: bool,
+    // Whether the data item on top of the stack is the content of tag 1,
+    // which may be an integer epoch time even when floats are strict.
+    epoch: bool,
+}
+
+/// The representation of a struct being decoded.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum StructRepr {
+    /// A map from field name to value.
+    Map,
+    /// A struct with one field, which may turn out to be a newtype once the
+    /// name of its field is known.
+    MaybeNewtype,
+    /// A tuple struct with one field, represented by the field's value.
+    Newtype,
+    /// An array of field values in declaration order.
+    Positional,
 }
 
 impl CborDecoder {
     pub fn new(val: Cbor) -> CborDecoder {
-        CborDecoder { stack: vec![val] }
+        CborDecoder {
+            stack: vec![val],
+            structs: vec![],
+            accept_array_as_map: false,
+            accept_field0_maps: false,
+            strict_floats: false,
+            epoch: false,
+        }
+    }
+
+    pub fn set_accept_array_as_map(&mut self, yes: bool) {
+        self.accept_array_as_map = yes;
+    }
+
+    pub fn set_accept_field0_maps(&mut self, yes: bool) {
+        self.accept_field0_maps = yes;
+    }
+
+    pub fn set_strict_floats(&mut self, yes: bool) {
+        self.strict_floats = yes;
+    }
+
+    /// Pops the next data item off the stack.
+    fn take(&mut self) -> Option<Cbor> {
+        self.epoch = false;
+        self.stack.pop()
     }
 
     pub fn pop(&mut self, expected: Type) -> CborResult<Cbor> {
-        match self.stack.pop() {
+        match self.take() {
             Some(Cbor::Bytes(v)) => Ok(Cbor::Array(
                 v.0.into_iter()
                  .map(|b| Cbor::Unsigned(CborUnsigned::UInt8(b)))
@@ -23,6 +82,7 @@ impl CborDecoder {
             )),
             Some(Cbor::Tag(tag)) => {
                 self.stack.push(*tag.data);
+                self.epoch = tag.tag == 1;
                 Ok(Cbor::Unsigned(CborUnsigned::UInt64(tag.tag)))
             }
             Some(v) => Ok(v),
@@ -33,13 +93,76 @@ impl CborDecoder {
     }
 
     pub fn pop_expect(&mut self, expected: &str) -> CborResult<Cbor> {
-        match self.stack.pop() {
+        match self.take() {
             Some(v) => Ok(v),
             None => Err(self.errstr(format!(
                 "No data items left (expected {}).", expected))),
         }
     }
 
+    fn is_array(&self) -> bool {
+        match self.stack.last() {
+            Some(&Cbor::Array(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Replaces the array of a positional struct on top of the stack with
+    /// its field values, so that the first field is on top.
+    fn unpack_fields(&mut self, len: usize) -> CborResult<()> {
+        let fields = match self.take() {
+            Some(Cbor::Array(fields)) => fields,
+            _ => unreachable!(),
+        };
+        if fields.len() != len {
+            return Err(self.errstr(format!(
+                "Expected struct with {} fields, but got array of length {}.",
+                len, fields.len())));
+        }
+        self.stack.extend(fields.into_iter().rev());
+        Ok(())
+    }
+
+    /// Returns true if the next data item is a map whose only key is
+    /// `_field0`, which is how newtypes were encoded by older versions of
+    /// this crate, and that form is accepted.
+    fn is_field0_map(&self) -> bool {
+        if !self.accept_field0_maps {
+            return false;
+        }
+        match self.stack.last() {
+            Some(&Cbor::Map(ref map)) => {
+                map.len() == 1 && map.contains_key("_field0")
+            }
+            _ => false,
+        }
+    }
+
+    /// Decodes an array of `[key, value]` arrays like a map, with the
+    /// entries in the same order as the array.
+    fn read_pairs<T, F>(&mut self, pairs: Vec<Cbor>, f: F) -> CborResult<T>
+            where F: FnOnce(&mut CborDecoder, usize) -> CborResult<T> {
+        let len = pairs.len();
+        for pair in pairs.into_iter().rev() {
+            let mut kv = match pair {
+                Cbor::Array(kv) => kv,
+                v => {
+                    return Err(self.err(ReadError::mismatch(Type::Array, &v)))
+                }
+            };
+            if kv.len() != 2 {
+                return Err(self.errstr(format!(
+                    "Expected map entry as array of length 2, but got array \
+                     of length {}.", kv.len())));
+            }
+            // The key goes on top of its value.
+            let (v, k) = (kv.pop().unwrap(), kv.pop().unwrap());
+            self.stack.push(v);
+            self.stack.push(k);
+        }
+        f(self, len)
+    }
+
     pub fn err(&self, err: ReadError) -> CborError {
         CborError::Decode(err)
     }
@@ -64,8 +187,17 @@ macro_rules! read_signed {
         let v = try!($dec.pop($cbor_ty));
         match v {
             Cbor::Signed(v) => v.$to().map_err(CborError::Decode),
-            Cbor::Unsigned(v) =>
-                v.$tou().map(|n| n as $ty).map_err(CborError::Decode),
+            Cbor::Unsigned(v) => match v.$tou() {
+                // An unsigned integer that's too big would wrap around and
+                // become negative.
+                Ok(n) if n as u64 > <$ty>::max_value() as u64 => {
+                    Err($dec.errstr(format!(
+                        "Unsigned integer {} does not fit in {}.",
+                        n, stringify!($ty))))
+                }
+                Ok(n) => Ok(n as $ty),
+                Err(err) => Err(CborError::Decode(err)),
+            },
             ref v => return Err($dec.err(ReadError::mismatch($cbor_ty, v))),
         }
     });
@@ -74,12 +206,14 @@ macro_rules! read_signed {
 macro_rules! read_float {
     ($dec:ident, $ty:ty, $cbor_ty:expr,
      $to:ident, $toi:ident, $tou:ident) => ({
+        // An integer epoch time in tag 1 is always allowed.
+        let ints = !$dec.strict_floats || $dec.epoch;
         let v = try!($dec.pop($cbor_ty));
         match v {
             Cbor::Float(v) => v.$to().map_err(CborError::Decode),
-            Cbor::Signed(v) =>
+            Cbor::Signed(v) if ints =>
                 v.$toi().map(|n| n as $ty).map_err(CborError::Decode),
-            Cbor::Unsigned(v) =>
+            Cbor::Unsigned(v) if ints =>
                 v.$tou().map(|n| n as $ty).map_err(CborError::Decode),
             ref v => return Err($dec.err(ReadError::mismatch($cbor_ty, v))),
         }
@@ -182,8 +316,38 @@ impl RustcDecoder for CborDecoder {
         mut f: F,
     ) -> CborResult<T>
     where F: FnMut(&mut CborDecoder, usize) -> CborResult<T> {
-        let name = match try!(self.pop_expect("Unicode or variant map")) {
+        if names == EPOCH_VARIANTS {
+            let idx = match self.stack.last() {
+                Some(&Cbor::Signed(_)) => 0,
+                Some(&Cbor::Unsigned(_)) => 1,
+                _ => 2,
+            };
+            return f(self, idx);
+        }
+        let name = match try!(self.pop_expect(
+                "Unicode, variant index or variant map")) {
             Cbor::Unicode(name) => name,
+            Cbor::Unsigned(idx) => {
+                let idx = try!(idx.to_usize().map_err(CborError::Decode));
+                if idx >= names.len() {
+                    return Err(self.errstr(format!(
+                        "Variant index {} is out of range for enum with {} \
+                         variants.", idx, names.len())));
+                }
+                return f(self, idx);
+            }
+            // The compact form, e.g., `{"Blue": 5}` or `{"Rgb": [1, 2, 3]}`.
+            Cbor::Map(map)
+                    if map.len() == 1 && !map.contains_key("variant") => {
+                let (name, fields) = map.into_iter().next().unwrap();
+                match fields {
+                    Cbor::Array(fields) => {
+                        self.stack.extend(fields.into_iter().rev());
+                    }
+                    field => self.stack.push(field),
+                }
+                name
+            }
             Cbor::Map(mut map) => {
                 let name = match map.remove("variant") {
                     Some(Cbor::Unicode(name)) => name,
@@ -206,7 +370,8 @@ impl RustcDecoder for CborDecoder {
                 name
             }
             v => return Err(self.errstr(format!(
-                "Expected Unicode string or variant map, but got {:?}",
+                "Expected Unicode string, variant index or variant map, \
+                 but got {:?}",
                 v.typ()))),
         };
         let idx = match names.iter().position(|&n| n == name) {
@@ -248,18 +413,32 @@ impl RustcDecoder for CborDecoder {
     fn read_struct<T, F>(
         &mut self,
         _s_name: &str,
-        _len: usize,
+        len: usize,
         f: F,
     ) -> CborResult<T>
     where F: FnOnce(&mut CborDecoder) -> CborResult<T> {
-        let val = try!(f(self));
+        let repr = if len == 1 {
+            StructRepr::MaybeNewtype
+        } else if self.is_array() {
+            try!(self.unpack_fields(len));
+            StructRepr::Positional
+        } else {
+            StructRepr::Map
+        };
+        self.structs.push(repr);
+        let val = f(self);
+        let repr = self.structs.pop().unwrap();
+        let val = try!(val);
+        if repr == StructRepr::Newtype || repr == StructRepr::Positional {
+            return Ok(val);
+        }
         // When we read a struct field, we pop the CBOR map off the stack,
         // find and remove the field name and its associated value, and then
         // push the map back on the stack. Therefore, when we're done
         // processing all the struct fields, we'll have an extraneous map
         // left on the stack. So pop it off. If this assert fails, we have a
         // bug in the decoder. ---AG
-        assert_eq!(self.stack.pop().unwrap().typ(), Type::Map);
+        assert_eq!(self.take().unwrap().typ(), Type::Map);
         // Do we want to check if the map popped off here is empty? If it's
         // not, that means the data contains more than what the struct
         // specifies. We should probably be relaxed and let it pass. ---AG
@@ -273,6 +452,24 @@ impl RustcDecoder for CborDecoder {
         f: F,
     ) -> CborResult<T>
     where F: FnOnce(&mut CborDecoder) -> CborResult<T> {
+        if self.structs.last() == Some(&StructRepr::MaybeNewtype) {
+            // Derived decoders name the fields of tuple structs `_fieldN`.
+            let repr = if f_name == "_field0" && !self.is_field0_map() {
+                StructRepr::Newtype
+            } else if self.is_array() {
+                try!(self.unpack_fields(1));
+                StructRepr::Positional
+            } else {
+                StructRepr::Map
+            };
+            *self.structs.last_mut().unwrap() = repr;
+        }
+        match self.structs.last() {
+            Some(&StructRepr::Newtype) | Some(&StructRepr::Positional) => {
+                return f(self);
+            }
+            _ => {}
+        }
         let mut map = match try!(self.pop(Type::Map)) {
             Cbor::Map(map) => map,
             v => return Err(self.err(ReadError::mismatch(Type::Map, &v))),
@@ -353,7 +550,9 @@ impl RustcDecoder for CborDecoder {
 
     fn read_option<T, F>(&mut self, mut f: F) -> CborResult<T>
             where F: FnMut(&mut CborDecoder, bool) -> CborResult<T> {
-        match try!(self.pop(Type::Any)) {
+        // The data item is put back untouched so that types which rely on
+        // seeing a byte string or tag (like `CborBytes`) decode as usual.
+        match try!(self.pop_expect("null or any data item")) {
             Cbor::Null => f(self, false),
             v => { self.stack.push(v); f(self, true) }
         }
@@ -379,6 +578,9 @@ impl RustcDecoder for CborDecoder {
             where F: FnOnce(&mut CborDecoder, usize) -> CborResult<T> {
         let map = match try!(self.pop(Type::Map)) {
             Cbor::Map(v) => v,
+            Cbor::Array(pairs) if self.accept_array_as_map => {
+                return self.read_pairs(pairs, f);
+            }
             v => return Err(self.err(ReadError::mismatch(Type::Map, &v))),
         };
         let len = map.len();
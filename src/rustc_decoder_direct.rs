@@ -58,8 +58,16 @@ impl<R: io::Read> CborDecoder<R> {
     }
 
     fn read_len(&mut self, first: Option<u8>) -> CborResult<usize> {
-        // TODO: Should really check for overflow here... ---AG
-        self.read_uint(first.map(|n| n & 0b000_11111), 64).map(|n| n as usize)
+        let n = try!(self.read_uint(first.map(|n| n & 0b000_11111), 64));
+        self.to_usize(n)
+    }
+
+    fn to_usize(&self, n: u64) -> CborResult<usize> {
+        if n > ::std::usize::MAX as u64 {
+            return Err(self.errstr(format!(
+                "Unsigned integer {} does not fit in a usize.", n)));
+        }
+        Ok(n as usize)
     }
 
     fn read_type(&mut self, expected: Type)
@@ -184,8 +192,8 @@ impl<R: io::Read> RustcDecoder for CborDecoder<R> {
     }
 
     fn read_usize(&mut self) -> CborResult<usize> {
-        // Shouldn't this fail if we read a u64 but usize is only 32 bits?
-        Ok(try!(self.read_uint(None, 64)) as usize)
+        let n = try!(self.read_uint(None, 64));
+        self.to_usize(n)
     }
 
     fn read_u64(&mut self) -> CborResult<u64> {
@@ -205,8 +213,12 @@ impl<R: io::Read> RustcDecoder for CborDecoder<R> {
     }
 
     fn read_isize(&mut self) -> CborResult<isize> {
-        // Shouldn't this fail if we read a i64 but isize is only 32 bits?
-        Ok(try!(self.read_int(None, 64)) as isize)
+        let n = try!(self.read_int(None, 64));
+        if n < ::std::isize::MIN as i64 || n > ::std::isize::MAX as i64 {
+            return Err(self.errstr(format!(
+                "Integer {} does not fit in an isize.", n)));
+        }
+        Ok(n as isize)
     }
 
     fn read_i64(&mut self) -> CborResult<i64> {
@@ -0,0 +1,40 @@
+use bytes::Bytes;
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+use CborBytes;
+
+/// A byte string held in a shared `bytes::Bytes` buffer.
+///
+/// This encodes and decodes exactly like `CborBytes`. Decoding it with
+/// `Decodable` copies the bytes, like any other decoding. To slice byte
+/// strings out of a `Bytes` buffer without copying, decode with
+/// `Decoder::from_shared_bytes` and `Decoder::read_shared_bytes` instead.
+#[derive(Clone, Debug, Default, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CborSharedBytes(pub Bytes);
+
+impl From<Bytes> for CborSharedBytes {
+    fn from(v: Bytes) -> CborSharedBytes { CborSharedBytes(v) }
+}
+
+impl ::std::ops::Deref for CborSharedBytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] { &self.0 }
+}
+
+impl Encodable for CborSharedBytes {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        // The same as the derived impl of `CborBytes`, which the encoder
+        // recognizes by name, but without copying into a `Vec`.
+        e.emit_struct("CborBytes", 1, |e| {
+            e.emit_struct_field("_field0", 0, |e| self.0[..].encode(e))
+        })
+    }
+}
+
+impl Decodable for CborSharedBytes {
+    fn decode<D: Decoder>(d: &mut D) -> Result<CborSharedBytes, D::Error> {
+        let bytes: CborBytes = try!(Decodable::decode(d));
+        Ok(CborSharedBytes(Bytes::from(bytes.0)))
+    }
+}
@@ -0,0 +1,463 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, SecondsFormat, TimeZone, Utc};
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+use {CborBytes, CborTagEncode};
+use rustc_decoder::EPOCH_VARIANTS;
+
+/// An epoch-based date/time (tag 1).
+///
+/// When decoding, the tagged data item may be an unsigned integer, a
+/// negative integer (for times before the epoch) or a floating point number.
+/// Sub-second precision is kept when the data item is a float. Any other data
+/// item is an error.
+///
+/// When encoding, an integer is written if the time falls on a whole second.
+/// Otherwise, a 64 bit float is written.
+///
+/// # Example
+///
+/// ```rust
+/// use std::time::{Duration, UNIX_EPOCH};
+/// use cbor::{CborTime, Decoder, Encoder};
+///
+/// let time = CborTime(UNIX_EPOCH + Duration::from_secs(1363896240));
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[time]).unwrap();
+/// assert_eq!(enc.as_bytes(), &[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0]);
+///
+/// let mut dec = Decoder::from_bytes(enc.as_bytes());
+/// assert_eq!(dec.decode::<CborTime>().next().unwrap().unwrap(), time);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct CborTime(pub SystemTime);
+
+impl Encodable for CborTime {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        match self.0.duration_since(UNIX_EPOCH) {
+            Ok(d) => {
+                if d.subsec_nanos() == 0 {
+                    CborTagEncode::new(1, &d.as_secs()).encode(e)
+                } else {
+                    CborTagEncode::new(1, &secs_f64(d)).encode(e)
+                }
+            }
+            Err(err) => {
+                let d = err.duration();
+                if d.subsec_nanos() == 0 {
+                    CborTagEncode::new(1, &-(d.as_secs() as i64)).encode(e)
+                } else {
+                    CborTagEncode::new(1, &-secs_f64(d)).encode(e)
+                }
+            }
+        }
+    }
+}
+
+impl Decodable for CborTime {
+    fn decode<D: Decoder>(d: &mut D) -> Result<CborTime, D::Error> {
+        try!(expect_tag(d, 1));
+        let (time, secs) = match try!(read_epoch(d)) {
+            Epoch::Unsigned(n) => {
+                (UNIX_EPOCH.checked_add(Duration::from_secs(n)), n.to_string())
+            }
+            Epoch::Signed(n) if n >= 0 => {
+                let after = Duration::from_secs(n as u64);
+                (UNIX_EPOCH.checked_add(after), n.to_string())
+            }
+            Epoch::Signed(n) => {
+                let before = Duration::from_secs(n.wrapping_neg() as u64);
+                (UNIX_EPOCH.checked_sub(before), n.to_string())
+            }
+            Epoch::Float(secs) => {
+                if !secs.is_finite() {
+                    return Err(d.error(&format!(
+                        "Expected finite epoch time in tag 1, but got {}.",
+                        secs)));
+                }
+                let time = if secs >= 0.0 {
+                    UNIX_EPOCH.checked_add(duration_from_secs(secs))
+                } else {
+                    UNIX_EPOCH.checked_sub(duration_from_secs(-secs))
+                };
+                (time, secs.to_string())
+            }
+        };
+        match time {
+            Some(time) => Ok(CborTime(time)),
+            None => Err(d.error(&format!(
+                "Epoch time in tag 1 is out of range: {}.", secs))),
+        }
+    }
+}
+
+/// The content of tag 1, with integers kept apart from floats.
+enum Epoch {
+    Signed(i64),
+    Unsigned(u64),
+    Float(f64),
+}
+
+/// Reads the content of tag 1 without converting integers to floats.
+fn read_epoch<D: Decoder>(d: &mut D) -> Result<Epoch, D::Error> {
+    d.read_enum("CborEpoch", |d| {
+        d.read_enum_variant(EPOCH_VARIANTS, |d, idx| Ok(match idx {
+            0 => Epoch::Signed(try!(d.read_enum_variant_arg(0, D::read_i64))),
+            1 => {
+                Epoch::Unsigned(try!(d.read_enum_variant_arg(0, D::read_u64)))
+            }
+            _ => Epoch::Float(try!(d.read_enum_variant_arg(0, D::read_f64))),
+        }))
+    })
+}
+
+macro_rules! text_tag {
+    ($(#[$attr:meta])* struct $name:ident($tag:expr);) => {
+        $(#[$attr])*
+        ///
+        /// The tagged data item must be a text string, which is kept as is.
+        /// Decoding fails if the tag or the data item is anything else.
+        #[derive(Clone, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+        pub struct $name(pub String);
+
+        impl Encodable for $name {
+            fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+                CborTagEncode::new($tag, &self.0).encode(e)
+            }
+        }
+
+        impl Decodable for $name {
+            fn decode<D: Decoder>(d: &mut D) -> Result<$name, D::Error> {
+                try!(expect_tag(d, $tag));
+                Ok($name(try!(d.read_str())))
+            }
+        }
+    }
+}
+
+text_tag! {
+    /// A regular expression (tag 35).
+    ///
+    /// The expression is not compiled or checked in any way.
+    struct CborRegex(35);
+}
+
+text_tag! {
+    /// A MIME message, including all headers (tag 36).
+    struct CborMime(36);
+}
+
+/// A UUID (tag 37).
+///
+/// The tagged data item must be a byte string of exactly 16 bytes. When the
+/// `uuid` feature is enabled, this converts to and from `uuid::Uuid`.
+///
+/// # Example
+///
+/// ```rust
+/// use cbor::{CborUuid, Decoder, Encoder};
+///
+/// let id = CborUuid([0x55; 16]);
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[id]).unwrap();
+/// assert_eq!(&enc.as_bytes()[..3], &[0xd8, 0x25, 0x50]);
+///
+/// let mut dec = Decoder::from_bytes(enc.as_bytes());
+/// assert_eq!(dec.decode::<CborUuid>().next().unwrap().unwrap(), id);
+/// ```
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CborUuid(pub [u8; 16]);
+
+impl Encodable for CborUuid {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        CborTagEncode::new(37, &CborBytes(self.0.to_vec())).encode(e)
+    }
+}
+
+impl Decodable for CborUuid {
+    fn decode<D: Decoder>(d: &mut D) -> Result<CborUuid, D::Error> {
+        try!(expect_tag(d, 37));
+        let bytes: Vec<u8> = try!(Decodable::decode(d));
+        if bytes.len() != 16 {
+            return Err(d.error(&format!(
+                "Expected 16 byte UUID in tag 37, but got {} bytes.",
+                bytes.len())));
+        }
+        let mut uuid = [0; 16];
+        uuid.copy_from_slice(&bytes);
+        Ok(CborUuid(uuid))
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<::uuid::Uuid> for CborUuid {
+    fn from(uuid: ::uuid::Uuid) -> CborUuid {
+        CborUuid(*uuid.as_bytes())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl From<CborUuid> for ::uuid::Uuid {
+    fn from(uuid: CborUuid) -> ::uuid::Uuid {
+        ::uuid::Uuid::from_bytes(uuid.0)
+    }
+}
+
+/// A date/time for use with `chrono` (tag 0 or tag 1), which is written as
+/// a standard date/time string (tag 0).
+///
+/// When decoding, either a tag 0 string in RFC 3339 format (with any
+/// offset, which is converted to UTC) or a tag 1 epoch-based date/time (see
+/// `CborTime`) is accepted. Sub-second precision is kept in both cases.
+///
+/// When encoding, the time is written in UTC (with a `Z` suffix), with as
+/// many fractional digits (0, 3, 6 or 9) as are needed to keep it exact.
+/// Use `CborEpochDateTime` to write tag 1 instead.
+///
+/// This requires the `chrono` feature.
+///
+/// # Example
+///
+/// ```rust
+/// # extern crate chrono;
+/// # extern crate cbor;
+/// # fn main() {
+/// use chrono::{TimeZone, Utc};
+/// use cbor::{CborDateTime, Decoder, Encoder};
+///
+/// let time = CborDateTime(Utc.timestamp_opt(1363896240, 0).unwrap());
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[time]).unwrap();
+/// assert_eq!(&enc.as_bytes()[..2], &[0xc0, 0x74]);
+/// assert_eq!(&enc.as_bytes()[2..], &b"2013-03-21T20:04:00Z"[..]);
+///
+/// let mut dec = Decoder::from_bytes(enc.as_bytes());
+/// assert_eq!(dec.decode::<CborDateTime>().next().unwrap().unwrap(), time);
+/// # }
+/// ```
+#[cfg(feature = "chrono")]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CborDateTime(pub DateTime<Utc>);
+
+/// A date/time for use with `chrono` (tag 0 or tag 1), which is written as
+/// an epoch-based date/time (tag 1).
+///
+/// This decodes exactly like `CborDateTime`. When encoding, an integer is
+/// written if the time falls on a whole second. Otherwise, a 64 bit float is
+/// written, which may lose some sub-second precision.
+///
+/// This requires the `chrono` feature.
+#[cfg(feature = "chrono")]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+pub struct CborEpochDateTime(pub DateTime<Utc>);
+
+#[cfg(feature = "chrono")]
+impl Encodable for CborDateTime {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        let s = self.0.to_rfc3339_opts(SecondsFormat::AutoSi, true);
+        CborTagEncode::new(0, &s).encode(e)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Decodable for CborDateTime {
+    fn decode<D: Decoder>(d: &mut D) -> Result<CborDateTime, D::Error> {
+        decode_datetime(d).map(CborDateTime)
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Encodable for CborEpochDateTime {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        let secs = self.0.timestamp();
+        let nanos = self.0.timestamp_subsec_nanos();
+        if nanos == 0 {
+            CborTagEncode::new(1, &secs).encode(e)
+        } else {
+            let secs = secs as f64 + nanos as f64 / 1e9;
+            CborTagEncode::new(1, &secs).encode(e)
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl Decodable for CborEpochDateTime {
+    fn decode<D: Decoder>(d: &mut D) -> Result<CborEpochDateTime, D::Error> {
+        decode_datetime(d).map(CborEpochDateTime)
+    }
+}
+
+/// Decodes a tag 0 or tag 1 date/time.
+#[cfg(feature = "chrono")]
+fn decode_datetime<D: Decoder>(d: &mut D)
+                              -> Result<DateTime<Utc>, D::Error> {
+    match try!(d.read_u64()) {
+        0 => {
+            let s = try!(d.read_str());
+            match DateTime::parse_from_rfc3339(&s) {
+                Ok(time) => Ok(time.with_timezone(&Utc)),
+                Err(err) => Err(d.error(&format!(
+                    "Invalid date/time string in tag 0 ({}): {}", err, s))),
+            }
+        }
+        1 => {
+            let secs = try!(d.read_f64());
+            let whole = secs.floor();
+            let nanos = ((secs - whole) * 1e9).round();
+            let (whole, nanos) = if nanos >= 1e9 {
+                (whole + 1.0, 0.0)
+            } else {
+                (whole, nanos)
+            };
+            let time = if whole.is_finite() && whole.abs() < 1e17 {
+                Utc.timestamp_opt(whole as i64, nanos as u32).single()
+            } else {
+                None
+            };
+            match time {
+                Some(time) => Ok(time),
+                None => Err(d.error(&format!(
+                    "Epoch time in tag 1 is out of range: {}.", secs))),
+            }
+        }
+        tag => Err(d.error(&format!(
+            "Expected tag 0 or tag 1, but got tag {}.", tag))),
+    }
+}
+
+/// A packed vector of booleans.
+///
+/// Each boolean takes up a single bit, so this is much more compact than
+/// encoding a `Vec<bool>`, which takes up a byte per element. This isn't a
+/// standard CBOR type, so it is written under the application specific tag
+/// `CborBitVec::TAG` (which is not registered with IANA). The tagged data
+/// item is an array of the number of booleans and a byte string with the
+/// bits, where the first boolean is the least significant bit of the first
+/// byte.
+///
+/// # Example
+///
+/// ```rust
+/// use cbor::{CborBitVec, Decoder, Encoder};
+///
+/// let bits = CborBitVec(vec![true, false, true]);
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[&bits]).unwrap();
+///
+/// let mut dec = Decoder::from_bytes(enc.as_bytes());
+/// assert_eq!(dec.decode::<CborBitVec>().next().unwrap().unwrap(), bits);
+/// ```
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct CborBitVec(pub Vec<bool>);
+
+impl CborBitVec {
+    /// The tag number used for packed boolean vectors.
+    pub const TAG: u64 = 60000;
+}
+
+impl Encodable for CborBitVec {
+    fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+        let mut packed = vec![0u8; (self.0.len() + 7) / 8];
+        for (i, _) in self.0.iter().enumerate().filter(|&(_, &b)| b) {
+            packed[i / 8] |= 1 << (i % 8);
+        }
+        let data = (self.0.len() as u64, CborBytes(packed));
+        CborTagEncode::new(CborBitVec::TAG, &data).encode(e)
+    }
+}
+
+impl Decodable for CborBitVec {
+    fn decode<D: Decoder>(d: &mut D) -> Result<CborBitVec, D::Error> {
+        try!(expect_tag(d, CborBitVec::TAG));
+        let (len, packed): (usize, CborBytes) = try!(Decodable::decode(d));
+        // Computed this way so that a huge length can't overflow.
+        let want = len / 8 + (len % 8 != 0) as usize;
+        if packed.len() != want {
+            return Err(d.error(&format!(
+                "Expected {} bytes for {} packed booleans, but got {}.",
+                want, len, packed.len())));
+        }
+        Ok(CborBitVec((0..len).map(|i| packed[i / 8] & (1 << (i % 8)) != 0)
+                              .collect()))
+    }
+}
+
+/// Decode the data item of a tag, failing unless the tag number is `tag`.
+///
+/// This is for writing `Decodable` impls of types that are encoded under
+/// their own tag (with `CborTagEncode`). The tag number is read and checked,
+/// and then `f` decodes the data item. Any decoding method can be used in
+/// `f`, so a tagged map can be decoded into a struct with `read_struct`, or
+/// the data item can be decoded as some other type with
+/// `Decodable::decode`.
+///
+/// # Example
+///
+/// An RPC error encoded as `1001({"code": 7, "message": "no"})`:
+///
+/// ```rust
+/// # extern crate rustc_serialize;
+/// # extern crate cbor;
+/// # fn main() {
+/// use cbor::{Decoder, read_tagged};
+/// use rustc_serialize::Decodable;
+///
+/// #[derive(Debug, PartialEq)]
+/// struct RpcError {
+///     code: i64,
+///     message: String,
+/// }
+///
+/// impl Decodable for RpcError {
+///     fn decode<D: rustc_serialize::Decoder>(d: &mut D)
+///                                           -> Result<RpcError, D::Error> {
+///         read_tagged(d, 1001, |d| d.read_struct("RpcError", 2, |d| {
+///             Ok(RpcError {
+///                 code: try!(d.read_struct_field(
+///                     "code", 0, Decodable::decode)),
+///                 message: try!(d.read_struct_field(
+///                     "message", 1, Decodable::decode)),
+///             })
+///         }))
+///     }
+/// }
+///
+/// let mut bytes = vec![0xd9, 0x03, 0xe9, 0xa2];
+/// bytes.extend(b"\x64code\x07\x67message\x62no");
+/// let mut dec = Decoder::from_bytes(bytes);
+/// let err: RpcError = dec.decode().next().unwrap().unwrap();
+/// assert_eq!(err, RpcError { code: 7, message: "no".to_owned() });
+/// # }
+/// ```
+pub fn read_tagged<D, T, F>(d: &mut D, tag: u64, f: F) -> Result<T, D::Error>
+        where D: Decoder, F: FnOnce(&mut D) -> Result<T, D::Error> {
+    try!(expect_tag(d, tag));
+    f(d)
+}
+
+/// Reads a tag number and fails unless it is equal to `tag`.
+fn expect_tag<D: Decoder>(d: &mut D, tag: u64) -> Result<(), D::Error> {
+    let got = try!(d.read_u64());
+    if got != tag {
+        return Err(d.error(&format!(
+            "Expected tag {}, but got tag {}.", tag, got)));
+    }
+    Ok(())
+}
+
+fn secs_f64(d: Duration) -> f64 {
+    d.as_secs() as f64 + d.subsec_nanos() as f64 / 1e9
+}
+
+fn duration_from_secs(secs: f64) -> Duration {
+    let whole = secs.trunc();
+    let nanos = ((secs - whole) * 1e9).round() as u32;
+    // `Duration::new` carries nanoseconds that round up to a whole second.
+    Duration::new(whole as u64, nanos)
+}
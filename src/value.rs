@@ -0,0 +1,574 @@
+use std::collections::HashMap;
+use std::collections::hash_map;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Index;
+
+use rustc_serialize::hex::ToHex;
+use rustc_serialize::json::Json;
+
+use {
+    Cbor, CborBytes, CborFloat, CborSigned, CborTag, CborUnsigned, ReadError,
+    Type,
+};
+
+static NULL: Cbor = Cbor::Null;
+
+impl Cbor {
+    /// Returns `true` if this is a null data item.
+    pub fn is_null(&self) -> bool {
+        *self == Cbor::Null
+    }
+
+    /// Returns the value of this data item if it is a boolean.
+    pub fn as_bool(&self) -> Option<bool> {
+        match *self {
+            Cbor::Bool(v) => Some(v),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of this data item if it is an unsigned integer.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Cbor::Unsigned(v) => v.to_u64().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of this data item if it is an integer of either
+    /// sign that fits in an `i64`.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Cbor::Unsigned(v) => {
+                let v = v.to_u64().unwrap();
+                if v > ::std::i64::MAX as u64 { None } else { Some(v as i64) }
+            }
+            Cbor::Signed(v) => v.to_i64().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of this data item if it is a floating point number
+    /// of any width.
+    pub fn as_f64(&self) -> Option<f64> {
+        match *self {
+            Cbor::Float(v) => v.to_f64().ok(),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of this data item if it is a Unicode string.
+    pub fn as_str(&self) -> Option<&str> {
+        match *self {
+            Cbor::Unicode(ref s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Returns the value of this data item if it is a byte string.
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match *self {
+            Cbor::Bytes(CborBytes(ref bytes)) => Some(bytes),
+            _ => None,
+        }
+    }
+
+    /// Returns the elements of this data item if it is an array.
+    pub fn as_array(&self) -> Option<&Vec<Cbor>> {
+        match *self {
+            Cbor::Array(ref vals) => Some(vals),
+            _ => None,
+        }
+    }
+
+    /// Returns the entries of this data item if it is a map.
+    pub fn as_map(&self) -> Option<&HashMap<String, Cbor>> {
+        match *self {
+            Cbor::Map(ref map) => Some(map),
+            _ => None,
+        }
+    }
+
+    /// Returns the value for `key` if this data item is a map that has it.
+    pub fn get(&self, key: &str) -> Option<&Cbor> {
+        self.as_map().and_then(|map| map.get(key))
+    }
+
+    /// Returns a mutable reference to the value for `key` if this data item
+    /// is a map that has it.
+    pub fn get_mut(&mut self, key: &str) -> Option<&mut Cbor> {
+        match *self {
+            Cbor::Map(ref mut map) => map.get_mut(key),
+            _ => None,
+        }
+    }
+
+    /// Returns a mutable reference to the element at index `i` if this data
+    /// item is an array that has it.
+    pub fn get_index_mut(&mut self, i: usize) -> Option<&mut Cbor> {
+        match *self {
+            Cbor::Array(ref mut vals) => vals.get_mut(i),
+            _ => None,
+        }
+    }
+
+    /// Looks up a value by a JSON Pointer (RFC 6901), e.g., `/users/0/name`.
+    ///
+    /// Each segment of the pointer is a map key or an array index, and `~1`
+    /// and `~0` in a segment stand for `/` and `~`. The empty pointer refers
+    /// to this data item itself. Tags are not looked through. If there is no
+    /// value at the pointer (or it is malformed), `None` is returned.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use cbor::Value;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("a/b".to_owned(), Value::from(vec![Value::from(1)]));
+    /// let v = Value::from(map);
+    /// assert_eq!(v.pointer("/a~1b/0"), Some(&Value::from(1)));
+    /// assert_eq!(v.pointer("/a~1b/1"), None);
+    /// ```
+    pub fn pointer(&self, pointer: &str) -> Option<&Cbor> {
+        let segs = match pointer_segments(pointer) {
+            Some(segs) => segs,
+            None => return None,
+        };
+        let mut v = self;
+        for seg in segs {
+            v = match *v {
+                Cbor::Map(ref map) => match map.get(&seg) {
+                    Some(v) => v,
+                    None => return None,
+                },
+                Cbor::Array(ref vals) => {
+                    match array_index(&seg).and_then(|i| vals.get(i)) {
+                        Some(v) => v,
+                        None => return None,
+                    }
+                }
+                _ => return None,
+            };
+        }
+        Some(v)
+    }
+
+    /// Like `pointer`, but returns a mutable reference, so that a value can
+    /// be changed in place.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use std::collections::HashMap;
+    /// use cbor::Value;
+    ///
+    /// let mut map = HashMap::new();
+    /// map.insert("xs".to_owned(), Value::from(vec![Value::from(1)]));
+    /// let mut v = Value::from(map);
+    /// *v.pointer_mut("/xs/0").unwrap() = Value::from("one");
+    /// assert_eq!(v["xs"][0].as_str(), Some("one"));
+    /// ```
+    pub fn pointer_mut(&mut self, pointer: &str) -> Option<&mut Cbor> {
+        let segs = match pointer_segments(pointer) {
+            Some(segs) => segs,
+            None => return None,
+        };
+        let mut v = self;
+        for seg in segs {
+            v = match *v {
+                Cbor::Map(ref mut map) => match map.get_mut(&seg) {
+                    Some(v) => v,
+                    None => return None,
+                },
+                Cbor::Array(ref mut vals) => {
+                    let i = match array_index(&seg) {
+                        Some(i) => i,
+                        None => return None,
+                    };
+                    match vals.get_mut(i) {
+                        Some(v) => v,
+                        None => return None,
+                    }
+                }
+                _ => return None,
+            };
+        }
+        Some(v)
+    }
+
+    /// Returns whether this data item and `other` are equal, except that
+    /// floats may differ by up to `epsilon`.
+    ///
+    /// Arrays, maps and tags are compared recursively. Floats of any width
+    /// are compared by value, and two NaNs are equal (unlike with `==`), as
+    /// are two infinities of the same sign. Every other data item must be
+    /// equal, so a float is never equal to an integer.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::Value;
+    ///
+    /// let a = Value::from(vec![Value::from(0.1f32), Value::from("x")]);
+    /// let b = Value::from(vec![Value::from(0.1f64), Value::from("x")]);
+    /// assert!(a != b);
+    /// assert!(a.approx_eq(&b, 1e-6));
+    /// ```
+    pub fn approx_eq(&self, other: &Cbor, epsilon: f64) -> bool {
+        match (self, other) {
+            (&Cbor::Float(a), &Cbor::Float(b)) => {
+                let (a, b) = (a.to_f64().unwrap(), b.to_f64().unwrap());
+                a == b
+                || (a.is_nan() && b.is_nan())
+                || (a - b).abs() <= epsilon
+            }
+            (&Cbor::Array(ref a), &Cbor::Array(ref b)) => {
+                a.len() == b.len()
+                && a.iter().zip(b).all(|(a, b)| a.approx_eq(b, epsilon))
+            }
+            (&Cbor::Map(ref a), &Cbor::Map(ref b)) => {
+                a.len() == b.len()
+                && a.iter().all(|(k, a)| {
+                    b.get(k).map_or(false, |b| a.approx_eq(b, epsilon))
+                })
+            }
+            (&Cbor::Tag(ref a), &Cbor::Tag(ref b)) => {
+                a.tag == b.tag && a.data.approx_eq(&b.data, epsilon)
+            }
+            (a, b) => a == b,
+        }
+    }
+
+    /// Consumes this data item and returns an iterator over its entries if
+    /// it is a map, so that keys and values can be moved out without
+    /// cloning.
+    ///
+    /// Keys are Unicode strings, since those are the only keys a map can
+    /// have. If this isn't a map, the iterator is empty. Entries come in no
+    /// particular order.
+    pub fn into_iter_entries(self) -> hash_map::IntoIter<String, Cbor> {
+        match self {
+            Cbor::Map(map) => map.into_iter(),
+            _ => HashMap::new().into_iter(),
+        }
+    }
+}
+
+/// Look up the value for a map key.
+///
+/// This returns `Cbor::Null` (instead of panicking) if this isn't a map or
+/// the key is missing, so lookups can be chained.
+impl<'a> Index<&'a str> for Cbor {
+    type Output = Cbor;
+
+    fn index(&self, key: &'a str) -> &Cbor {
+        self.get(key).unwrap_or(&NULL)
+    }
+}
+
+/// Look up an element of an array.
+///
+/// This returns `Cbor::Null` (instead of panicking) if this isn't an array or
+/// the index is out of bounds, so lookups can be chained.
+impl Index<usize> for Cbor {
+    type Output = Cbor;
+
+    fn index(&self, i: usize) -> &Cbor {
+        self.as_array().and_then(|vals| vals.get(i)).unwrap_or(&NULL)
+    }
+}
+
+/// Splits a JSON Pointer into its unescaped segments.
+fn pointer_segments(pointer: &str) -> Option<Vec<String>> {
+    if pointer.is_empty() {
+        return Some(vec![]);
+    }
+    if !pointer.starts_with('/') {
+        return None;
+    }
+    Some(pointer[1..].split('/')
+                     .map(|seg| seg.replace("~1", "/").replace("~0", "~"))
+                     .collect())
+}
+
+/// Parses an array index in a JSON Pointer, which can't have leading
+/// zeros.
+fn array_index(seg: &str) -> Option<usize> {
+    if seg.is_empty() || (seg.len() > 1 && seg.starts_with('0'))
+            || !seg.bytes().all(|b| b'0' <= b && b <= b'9') {
+        return None;
+    }
+    seg.parse().ok()
+}
+
+macro_rules! from_unsigned {
+    ($($ty:ty => $variant:ident),*) => {$(
+        impl From<$ty> for Cbor {
+            fn from(v: $ty) -> Cbor {
+                Cbor::Unsigned(CborUnsigned::$variant(v))
+            }
+        }
+    )*}
+}
+
+macro_rules! from_signed {
+    ($($ty:ty => $uty:ty, $uvariant:ident, $variant:ident),*) => {$(
+        impl From<$ty> for Cbor {
+            fn from(v: $ty) -> Cbor {
+                if v >= 0 {
+                    Cbor::Unsigned(CborUnsigned::$uvariant(v as $uty))
+                } else {
+                    Cbor::Signed(CborSigned::$variant(v))
+                }
+            }
+        }
+    )*}
+}
+
+from_unsigned! {
+    u8 => UInt8, u16 => UInt16, u32 => UInt32, u64 => UInt64
+}
+
+from_signed! {
+    i8 => u8, UInt8, Int8,
+    i16 => u16, UInt16, Int16,
+    i32 => u32, UInt32, Int32,
+    i64 => u64, UInt64, Int64
+}
+
+impl From<bool> for Cbor {
+    fn from(v: bool) -> Cbor { Cbor::Bool(v) }
+}
+
+impl From<f32> for Cbor {
+    fn from(v: f32) -> Cbor { Cbor::Float(CborFloat::Float32(v)) }
+}
+
+impl From<f64> for Cbor {
+    fn from(v: f64) -> Cbor { Cbor::Float(CborFloat::Float64(v)) }
+}
+
+impl<'a> From<&'a str> for Cbor {
+    fn from(v: &'a str) -> Cbor { Cbor::Unicode(v.to_owned()) }
+}
+
+impl From<String> for Cbor {
+    fn from(v: String) -> Cbor { Cbor::Unicode(v) }
+}
+
+impl From<CborBytes> for Cbor {
+    fn from(v: CborBytes) -> Cbor { Cbor::Bytes(v) }
+}
+
+impl From<Vec<Cbor>> for Cbor {
+    fn from(v: Vec<Cbor>) -> Cbor { Cbor::Array(v) }
+}
+
+impl From<HashMap<String, Cbor>> for Cbor {
+    fn from(v: HashMap<String, Cbor>) -> Cbor { Cbor::Map(v) }
+}
+
+impl From<CborTag> for Cbor {
+    fn from(v: CborTag) -> Cbor { Cbor::Tag(v) }
+}
+
+macro_rules! try_from {
+    ($($ty:ty => $expected:ident, $as:ident;)*) => {$(
+        impl TryFrom<Cbor> for $ty {
+            type Error = ReadError;
+
+            fn try_from(v: Cbor) -> Result<$ty, ReadError> {
+                let expected = Type::$expected;
+                v.$as().ok_or_else(|| ReadError::mismatch(expected, &v))
+            }
+        }
+    )*}
+}
+
+try_from! {
+    bool => Bool, as_bool;
+    u64 => UInt64, as_u64;
+    i64 => Int64, as_i64;
+    f64 => Float64, as_f64;
+}
+
+impl TryFrom<Cbor> for String {
+    type Error = ReadError;
+
+    fn try_from(v: Cbor) -> Result<String, ReadError> {
+        match v {
+            Cbor::Unicode(s) => Ok(s),
+            v => Err(ReadError::mismatch(Type::Unicode, &v)),
+        }
+    }
+}
+
+impl TryFrom<Cbor> for CborBytes {
+    type Error = ReadError;
+
+    fn try_from(v: Cbor) -> Result<CborBytes, ReadError> {
+        match v {
+            Cbor::Bytes(bytes) => Ok(bytes),
+            v => Err(ReadError::mismatch(Type::Bytes, &v)),
+        }
+    }
+}
+
+impl TryFrom<Cbor> for Vec<Cbor> {
+    type Error = ReadError;
+
+    fn try_from(v: Cbor) -> Result<Vec<Cbor>, ReadError> {
+        match v {
+            Cbor::Array(vals) => Ok(vals),
+            v => Err(ReadError::mismatch(Type::Array, &v)),
+        }
+    }
+}
+
+impl TryFrom<Cbor> for HashMap<String, Cbor> {
+    type Error = ReadError;
+
+    fn try_from(v: Cbor) -> Result<HashMap<String, Cbor>, ReadError> {
+        match v {
+            Cbor::Map(map) => Ok(map),
+            v => Err(ReadError::mismatch(Type::Map, &v)),
+        }
+    }
+}
+
+/// Options for writing a data item in diagnostic notation, used by
+/// `Cbor::to_diag_with`.
+///
+/// The default options give the same output as `Cbor`'s `Display` impl, in
+/// which floats are written in the shortest form that reads back as the
+/// same value.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DiagOptions {
+    /// When set, floats are written with exactly this many digits after the
+    /// decimal point, which may lose precision. Scientific notation is never
+    /// used in this case.
+    pub float_precision: Option<usize>,
+    /// When enabled, floats are never written in scientific notation, e.g.,
+    /// `1e20` is written as `100000000000000000000.0`.
+    pub no_exponent: bool,
+}
+
+impl Cbor {
+    /// Returns this data item in diagnostic notation, with floats written
+    /// as configured by `opts`.
+    ///
+    /// Unless `float_precision` is `Some(0)`, finite floats always contain a
+    /// decimal point (or an exponent), so they can't be confused with
+    /// integers. NaN and infinities are written
+    /// as `NaN`, `Infinity` and `-Infinity` regardless of the options.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use cbor::{DiagOptions, Value};
+    ///
+    /// let v = Value::from(vec![Value::from(1e20), Value::from(2.0 / 3.0)]);
+    /// assert_eq!(v.to_string(), "[1e20, 0.6666666666666666]");
+    ///
+    /// let opts = DiagOptions {
+    ///     float_precision: Some(3),
+    ///     ..Default::default()
+    /// };
+    /// assert_eq!(v.to_diag_with(&opts),
+    ///            "[100000000000000000000.000, 0.667]");
+    /// ```
+    pub fn to_diag_with(&self, opts: &DiagOptions) -> String {
+        Diag { v: self, opts: opts }.to_string()
+    }
+}
+
+/// Writes the data item in the diagnostic notation of RFC 8949, e.g.,
+/// `{"a": [1, h'ff'], "b": 1(1363896240)}`.
+///
+/// Map entries are written in sorted key order, so the output is
+/// deterministic.
+impl fmt::Display for Cbor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        Diag { v: self, opts: &DiagOptions::default() }.fmt(f)
+    }
+}
+
+struct Diag<'a> {
+    v: &'a Cbor,
+    opts: &'a DiagOptions,
+}
+
+impl<'a> Diag<'a> {
+    fn nested(&self, v: &'a Cbor) -> Diag<'a> {
+        Diag { v: v, opts: self.opts }
+    }
+}
+
+impl<'a> fmt::Display for Diag<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.v {
+            Cbor::Break => write!(f, "break"),
+            Cbor::Undefined => write!(f, "undefined"),
+            Cbor::Simple(v) => write!(f, "simple({})", v),
+            Cbor::Null => write!(f, "null"),
+            Cbor::Bool(v) => write!(f, "{}", v),
+            Cbor::Unsigned(v) => write!(f, "{}", v.to_u64().unwrap()),
+            Cbor::Signed(v) => write!(f, "{}", v.to_i64().unwrap()),
+            Cbor::Float(v) => {
+                let v = v.to_f64().unwrap();
+                if v.is_nan() {
+                    write!(f, "NaN")
+                } else if v.is_infinite() {
+                    write!(f, "{}Infinity", if v < 0.0 { "-" } else { "" })
+                } else {
+                    write!(f, "{}", format_float(v, self.opts))
+                }
+            }
+            Cbor::Bytes(ref v) => write!(f, "h'{}'", v.to_hex()),
+            Cbor::Unicode(ref v) => write!(f, "{}", Json::String(v.clone())),
+            Cbor::Array(ref vals) => {
+                try!(write!(f, "["));
+                for (i, v) in vals.iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ", "));
+                    }
+                    try!(write!(f, "{}", self.nested(v)));
+                }
+                write!(f, "]")
+            }
+            Cbor::Map(ref map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by(|a, b| a.0.cmp(b.0));
+                try!(write!(f, "{{"));
+                for (i, (k, v)) in entries.into_iter().enumerate() {
+                    if i > 0 {
+                        try!(write!(f, ", "));
+                    }
+                    try!(write!(f, "{}: {}", Json::String(k.clone()),
+                                self.nested(v)));
+                }
+                write!(f, "}}")
+            }
+            Cbor::Tag(ref tag) => {
+                write!(f, "{}({})", tag.tag, self.nested(&tag.data))
+            }
+        }
+    }
+}
+
+/// Formats a finite float.
+fn format_float(v: f64, opts: &DiagOptions) -> String {
+    match opts.float_precision {
+        Some(precision) => format!("{:.*}", precision, v),
+        // `Display` never uses an exponent, while `Debug` does for very
+        // large and small numbers. Both are the shortest that round trip.
+        None if opts.no_exponent => {
+            let s = format!("{}", v);
+            if s.contains('.') { s } else { s + ".0" }
+        }
+        None => format!("{:?}", v),
+    }
+}
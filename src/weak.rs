@@ -0,0 +1,69 @@
+use std::fmt;
+use std::rc;
+use std::sync;
+
+use rustc_serialize::{Decodable, Decoder, Encodable, Encoder};
+
+macro_rules! weak_wrappers {
+    ($($name:ident($weak:path, $strong:expr)),*) => {$(
+        #[doc = concat!("A `", stringify!($weak), "` that can be encoded \
+                         and decoded.")]
+        ///
+        /// When encoding, the reference is upgraded. If the value is still
+        #[doc = concat!("alive (i.e., some `", $strong, "` still owns it), \
+                         the value is written.")]
+        /// Otherwise, null is written.
+        ///
+        /// When decoding, there is nothing that could own the decoded value,
+        /// so the result is always a dangling reference (as made by
+        /// `Weak::new`). The data item is still decoded as an `Option<T>`,
+        /// so invalid data is an error.
+        pub struct $name<T>(pub $weak);
+
+        impl<T> $name<T> {
+            /// Create a new dangling weak reference.
+            pub fn new() -> $name<T> {
+                $name(<$weak>::new())
+            }
+        }
+
+        impl<T> Default for $name<T> {
+            fn default() -> $name<T> { $name::new() }
+        }
+
+        impl<T> From<$weak> for $name<T> {
+            fn from(v: $weak) -> $name<T> { $name(v) }
+        }
+
+        impl<T> Clone for $name<T> {
+            fn clone(&self) -> $name<T> { $name(self.0.clone()) }
+        }
+
+        impl<T: fmt::Debug> fmt::Debug for $name<T> {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                fmt::Debug::fmt(&self.0, f)
+            }
+        }
+
+        impl<T: Encodable> Encodable for $name<T> {
+            fn encode<E: Encoder>(&self, e: &mut E) -> Result<(), E::Error> {
+                match self.0.upgrade() {
+                    Some(v) => v.encode(e),
+                    None => e.emit_nil(),
+                }
+            }
+        }
+
+        impl<T: Decodable> Decodable for $name<T> {
+            fn decode<D: Decoder>(d: &mut D) -> Result<$name<T>, D::Error> {
+                let _: Option<T> = try!(Decodable::decode(d));
+                Ok($name::new())
+            }
+        }
+    )*}
+}
+
+weak_wrappers! {
+    CborRcWeak(rc::Weak<T>, "Rc"),
+    CborArcWeak(sync::Weak<T>, "Arc")
+}
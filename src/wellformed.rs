@@ -0,0 +1,197 @@
+use std::ops::Range;
+
+use {CborError, CborResult, ReadError, Type};
+
+/// Split a buffer of concatenated top-level CBOR data items into the byte
+/// range of each item.
+///
+/// Every item is checked for well-formedness, but nothing is decoded and
+/// nothing is allocated on behalf of the data. In particular, lengths are
+/// checked against the number of bytes left in the buffer, so a crafted
+/// length can't cause a huge allocation.
+///
+/// # Example
+///
+/// ```rust
+/// use cbor::{Decoder, Encoder, split_sequence};
+///
+/// let mut enc = Encoder::from_memory();
+/// enc.encode(&[vec![1, 2], vec![3]]).unwrap();
+/// let bytes = enc.into_bytes();
+///
+/// let ranges = split_sequence(&bytes).unwrap();
+/// assert_eq!(ranges, vec![0..3, 3..5]);
+///
+/// // Decode only the second item.
+/// let mut dec = Decoder::from_bytes(&bytes[ranges[1].clone()]);
+/// let second: Vec<u32> = dec.decode().next().unwrap().unwrap();
+/// assert_eq!(second, vec![3]);
+/// ```
+pub fn split_sequence(bytes: &[u8]) -> CborResult<Vec<Range<usize>>> {
+    let mut ranges = vec![];
+    let mut start = 0;
+    while start < bytes.len() {
+        let end = try!(item_end(bytes, start));
+        ranges.push(start..end);
+        start = end;
+    }
+    Ok(ranges)
+}
+
+/// Returns the offset just past the well-formed data item that starts at
+/// `start`.
+///
+/// This doesn't recurse, so deeply nested data can't overflow the stack.
+pub fn item_end(bytes: &[u8], start: usize) -> CborResult<usize> {
+    let mut w = Walker { bytes: bytes, pos: start };
+    // One entry for each enclosing array or map, which is the number of data
+    // items left in it, or `None` if it has an indefinite length.
+    let mut open: Vec<Option<u64>> = vec![];
+    loop {
+        let first = try!(w.byte());
+        if first == 0xff {
+            match open.pop() {
+                Some(None) => {}
+                _ => return Err(w.errat(ReadError::Other(
+                    "Found break outside of an indefinite length \
+                     data item.".to_owned()))),
+            }
+        } else {
+            match first >> 5 {
+                0 | 1 => { try!(w.definite(first)); }
+                2 | 3 => match try!(w.arg(first)) {
+                    Some(len) => try!(w.skip(len)),
+                    None => try!(w.skip_chunks(first >> 5)),
+                },
+                4 => match try!(w.arg(first)) {
+                    Some(0) => {}
+                    len => { open.push(len); continue; }
+                },
+                5 => match try!(w.arg(first)) {
+                    Some(0) => {}
+                    Some(len) => match len.checked_mul(2) {
+                        Some(n) => { open.push(Some(n)); continue; }
+                        None => return Err(w.errat(ReadError::Other(
+                            format!("Map length is too large: {}.", len)))),
+                    },
+                    None => { open.push(None); continue; }
+                },
+                // A tag is always followed by exactly one data item, which
+                // completes it.
+                6 => { try!(w.definite(first)); continue; }
+                _ => try!(w.simple(first)),
+            }
+        }
+        // A data item was completed. Close every array and map that it
+        // filled up.
+        loop {
+            match open.last_mut() {
+                None => return Ok(w.pos),
+                Some(&mut None) => break,
+                Some(&mut Some(ref mut left)) => {
+                    *left -= 1;
+                    if *left > 0 {
+                        break;
+                    }
+                }
+            }
+            open.pop();
+        }
+    }
+}
+
+struct Walker<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Walker<'a> {
+    fn byte(&mut self) -> CborResult<u8> {
+        match self.bytes.get(self.pos) {
+            Some(&b) => { self.pos += 1; Ok(b) }
+            None => Err(CborError::UnexpectedEOF),
+        }
+    }
+
+    fn skip(&mut self, n: u64) -> CborResult<()> {
+        if n > (self.bytes.len() - self.pos) as u64 {
+            return Err(CborError::UnexpectedEOF);
+        }
+        self.pos += n as usize;
+        Ok(())
+    }
+
+    fn uint(&mut self, nbytes: usize) -> CborResult<u64> {
+        let mut n = 0;
+        for _ in 0..nbytes {
+            n = (n << 8) | try!(self.byte()) as u64;
+        }
+        Ok(n)
+    }
+
+    /// Reads the argument of a header, or `None` for an indefinite length.
+    fn arg(&mut self, first: u8) -> CborResult<Option<u64>> {
+        Ok(Some(match first & 0b000_11111 {
+            n @ 0...23 => n as u64,
+            24 => try!(self.uint(1)),
+            25 => try!(self.uint(2)),
+            26 => try!(self.uint(4)),
+            27 => try!(self.uint(8)),
+            31 => return Ok(None),
+            v => {
+                let ty = Type::from_desc(first).unwrap_or(Type::Any);
+                return Err(self.errat(
+                    ReadError::InvalidAddValue { ty: ty, val: v }));
+            }
+        }))
+    }
+
+    /// Reads the argument of a header that can't have an indefinite length.
+    fn definite(&mut self, first: u8) -> CborResult<u64> {
+        match try!(self.arg(first)) {
+            Some(n) => Ok(n),
+            None => {
+                let ty = Type::from_desc(first).unwrap_or(Type::Any);
+                Err(self.errat(ReadError::InvalidAddValue { ty: ty, val: 31 }))
+            }
+        }
+    }
+
+    /// Skips the chunks of an indefinite length string with the given major
+    /// type, up to and including the terminating break.
+    fn skip_chunks(&mut self, major: u8) -> CborResult<()> {
+        loop {
+            let first = try!(self.byte());
+            if first == 0xff {
+                return Ok(());
+            }
+            if first >> 5 != major {
+                return Err(self.errat(ReadError::Other(format!(
+                    "Expected chunk with major type {} in indefinite length \
+                     string, but got byte {:#x}.", major, first))));
+            }
+            let len = try!(self.definite(first));
+            try!(self.skip(len));
+        }
+    }
+
+    /// Skips the rest of a data item with major type 7 (other than break).
+    fn simple(&mut self, first: u8) -> CborResult<()> {
+        match first & 0b000_11111 {
+            0...23 => Ok(()),
+            24 => match try!(self.byte()) {
+                v @ 0...31 => Err(self.errat(
+                    ReadError::Reserved { major: 7, add: v })),
+                _ => Ok(()),
+            },
+            25 => self.skip(2),
+            26 => self.skip(4),
+            27 => self.skip(8),
+            v => Err(self.errat(ReadError::Unassigned { major: 7, add: v })),
+        }
+    }
+
+    fn errat(&self, err: ReadError) -> CborError {
+        CborError::AtOffset { kind: err, offset: self.pos - 1 }
+    }
+}
@@ -1,15 +1,25 @@
 extern crate cbor;
+#[cfg(feature = "bytes")]
+extern crate bytes;
+#[cfg(feature = "chrono")]
+extern crate chrono;
 extern crate quickcheck;
 extern crate rand;
 extern crate rustc_serialize;
+#[cfg(feature = "uuid")]
+extern crate uuid;
 
 use std::collections::HashMap;
 use std::fmt::Debug;
 use rand::thread_rng;
 use rustc_serialize::{Decodable, Encodable};
+use rustc_serialize::hex::{FromHex, ToHex};
 use quickcheck::{QuickCheck, StdGen, Testable};
 
-use cbor::{Encoder, Decoder, DirectDecoder, Cbor, CborBytes, CborTagEncode};
+use cbor::{
+    Encoder, Decoder, DirectDecoder, Cbor, CborBytes, CborTagEncode, CborTime,
+    CborRegex, CborMime, CborInteger, CborUuid, split_sequence,
+};
 
 fn qc_sized<A: Testable>(f: A, size: u64) {
     QuickCheck::new()
@@ -217,3 +227,2073 @@ fn test_oom() {
    // let bad = vec![155u8, 0x00, 0xFF, 0xFF, 0xFF, 0x00, 0xFF, 0xFF, 0xFF];
    // assert!(Vec::<u32>::decode(&mut DirectDecoder::from_bytes(bad)).is_err());
 // }
+
+#[test]
+fn retain_array_drops_byte_strings() {
+    let mut cbor = readone(&encode(
+        (1, CborBytes(vec![1, 2, 3]), "a", CborBytes(vec![]))));
+    cbor.retain(|v| match *v { Cbor::Bytes(_) => false, _ => true });
+    assert_eq!(cbor, readone(&encode((1, "a"))));
+}
+
+#[test]
+fn retain_entries_drops_key() {
+    let mut map = HashMap::new();
+    map.insert("password".to_string(), vec![1]);
+    map.insert("user".to_string(), vec![2]);
+    let mut cbor = readone(&encode(&map));
+    cbor.retain_entries(|k, _| k != "password");
+
+    map.remove("password");
+    assert_eq!(cbor, readone(&encode(&map)));
+}
+
+#[test]
+fn decode_epoch_time_integer() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let time: CborTime = decode(&[0xc1, 0x1a, 0x51, 0x4b, 0x67, 0xb0]);
+    assert_eq!(time, CborTime(UNIX_EPOCH + Duration::from_secs(1363896240)));
+    round_trip(time);
+}
+
+#[test]
+fn decode_epoch_time_float() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let time: CborTime = decode(&[
+        0xc1, 0xfb, 0x41, 0xd4, 0x52, 0xd9, 0xec, 0x20, 0x00, 0x00,
+    ]);
+    let want = UNIX_EPOCH + Duration::new(1363896240, 500_000_000);
+    assert_eq!(time, CborTime(want));
+    round_trip(time);
+}
+
+#[test]
+fn decode_epoch_time_negative() {
+    use std::time::{Duration, UNIX_EPOCH};
+    let time: CborTime = decode(&[0xc1, 0x20]);
+    assert_eq!(time, CborTime(UNIX_EPOCH - Duration::from_secs(1)));
+    round_trip(time);
+    round_trip(CborTime(UNIX_EPOCH - Duration::new(1, 250_000_000)));
+}
+
+#[test]
+fn decode_epoch_time_integer_exact() {
+    use std::time::{Duration, UNIX_EPOCH};
+    // 2^53 + 1 seconds, which an f64 can't hold.
+    let secs = 9007199254740993;
+    let time: CborTime = decode(&[
+        0xc1, 0x1b, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+    ]);
+    assert_eq!(time, CborTime(UNIX_EPOCH + Duration::from_secs(secs)));
+    round_trip(time);
+    let time: CborTime = decode(&[
+        0xc1, 0x3b, 0x00, 0x20, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    ]);
+    assert_eq!(time, CborTime(UNIX_EPOCH - Duration::from_secs(secs)));
+    round_trip(time);
+}
+
+#[test]
+fn decode_epoch_time_bad_type() {
+    let mut dec = Decoder::from_bytes(vec![0xc1, 0x61, 0x61]);
+    assert!(dec.decode::<CborTime>().next().unwrap().is_err());
+}
+
+#[test]
+fn encode_tag_with_u64_data() {
+    assert_eq!(encode(CborTagEncode::new(1, &5u64)), vec![0xc1, 0x05]);
+    let bytes = vec![0xd8, 0x20, 0x63, 0x61, 0x62, 0x63];
+    assert_eq!(encode(readone(&bytes)), bytes);
+}
+
+#[test]
+fn forbid_floats() {
+    let mut enc = Encoder::from_memory();
+    enc.set_forbid_floats(true);
+    assert!(enc.encode(&[1.5f64]).is_err());
+    assert!(enc.encode(&[1.5f32]).is_err());
+    assert!(enc.encode(&[(1, vec![2.0f64])]).is_err());
+
+    let mut enc = Encoder::from_memory();
+    enc.set_forbid_floats(true);
+    enc.encode(&[1u32, 2, 3]).unwrap();
+    assert_eq!(enc.as_bytes(), &[1, 2, 3]);
+}
+
+#[test]
+fn decode_indefinite() {
+    // (_ h'0102', h'030405')
+    assert_eq!(decode::<CborBytes>(&[0x5f, 0x42, 1, 2, 0x43, 3, 4, 5, 0xff]),
+               CborBytes(vec![1, 2, 3, 4, 5]));
+    // (_ "strea", "ming")
+    let bytes = b"\x7f\x65strea\x64ming\xff";
+    assert_eq!(decode::<String>(bytes), "streaming".to_string());
+    // [_ 1, [2, 3], [_ 4, 5]]
+    let bytes = [0x9f, 0x01, 0x82, 0x02, 0x03, 0x9f, 0x04, 0x05, 0xff, 0xff];
+    assert_eq!(decode::<(i32, Vec<i32>, Vec<i32>)>(&bytes),
+               (1, vec![2, 3], vec![4, 5]));
+    // {_ "a": 1, "b": [_ 2, 3]}
+    let bytes = [0xbf, 0x61, 0x61, 0x01, 0x61, 0x62, 0x9f, 0x02, 0x03, 0xff,
+                 0xff];
+    let mut map = HashMap::new();
+    map.insert("a".to_string(), readone(&[0x01]));
+    map.insert("b".to_string(), readone(&[0x82, 0x02, 0x03]));
+    assert_eq!(readone(&bytes), Cbor::Map(map));
+}
+
+#[test]
+fn decode_indefinite_bad_chunk() {
+    // A text chunk inside an indefinite byte string.
+    let mut dec = Decoder::from_bytes(vec![0x5f, 0x61, 0x61, 0xff]);
+    assert!(dec.items().next().unwrap().is_err());
+    // A key without a value in an indefinite map.
+    let mut dec = Decoder::from_bytes(vec![0xbf, 0x61, 0x61, 0xff]);
+    assert!(dec.items().next().unwrap().is_err());
+}
+
+#[test]
+fn forbid_indefinite() {
+    let cases: &[&[u8]] = &[
+        &[0x5f, 0x41, 0x01, 0xff],
+        &[0x7f, 0x61, 0x61, 0xff],
+        &[0x9f, 0x01, 0xff],
+        &[0xbf, 0x61, 0x61, 0x01, 0xff],
+        &[0x81, 0x9f, 0xff],
+    ];
+    for bytes in cases {
+        let mut dec = Decoder::from_bytes(bytes.to_vec());
+        dec.set_forbid_indefinite(true);
+        assert!(dec.items().next().unwrap().is_err());
+
+        let mut dec = Decoder::from_bytes(bytes.to_vec());
+        assert!(dec.items().next().unwrap().is_ok());
+    }
+}
+
+#[test]
+fn newtype_is_transparent() {
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct UserId(u64);
+
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct Wrapped(UserId);
+
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct Named { id: UserId }
+
+    assert_eq!(encode(UserId(5)), encode(5u64));
+    assert_eq!(encode(Wrapped(UserId(5))), encode(5u64));
+    round_trip(UserId(5));
+    round_trip(Wrapped(UserId(5)));
+    round_trip(Named { id: UserId(5) });
+    round_trip(vec![UserId(1), UserId(2)]);
+}
+
+#[test]
+fn newtype_decodes_field_map() {
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct UserId(u64);
+
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct Counts(HashMap<String, u64>);
+
+    // {"_field0": 5}
+    let bytes = b"\xa1\x67_field0\x05";
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    assert!(dec.decode::<UserId>().next().unwrap().is_err());
+
+    let mut counts = HashMap::new();
+    counts.insert("_field0".to_owned(), 5);
+    assert_eq!(decode::<Counts>(bytes), Counts(counts));
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_accept_field0_maps(true);
+    assert_eq!(dec.decode::<UserId>().next().unwrap().unwrap(), UserId(5));
+}
+
+#[test]
+fn write_headers() {
+    let mut enc = Encoder::from_memory();
+    enc.write_array_header(3).unwrap();
+    enc.encode(&[1, 2]).unwrap();
+    enc.write_map_header(1).unwrap();
+    enc.encode(&["a"]).unwrap();
+    enc.encode(&[vec![true]]).unwrap();
+
+    let mut map = HashMap::new();
+    map.insert("a".to_string(), vec![true]);
+    let (a, b, c): (u8, u8, HashMap<String, Vec<bool>>) =
+        decode(enc.as_bytes());
+    assert_eq!((a, b, c), (1, 2, map));
+}
+
+#[test]
+fn roundtrip_binary_heap() {
+    use cbor::CborBinaryHeap;
+
+    let heap = CborBinaryHeap(vec![5, 1, 8, 3, 8].into_iter().collect());
+    let back: CborBinaryHeap<i32> = decode(&encode(heap.clone()));
+    assert_eq!(back.0.into_sorted_vec(), heap.0.into_sorted_vec());
+    assert!(decode::<CborBinaryHeap<i32>>(&encode(Vec::<i32>::new()))
+                .0.is_empty());
+}
+
+#[test]
+fn split_sequence_ranges() {
+    let mut bytes = encode(vec![1u32, 1000]);
+    bytes.extend(encode("abc"));
+    // {_ "a": [_ 1]}
+    bytes.extend(vec![0xbf, 0x61, 0x61, 0x9f, 0x01, 0xff, 0xff]);
+    assert_eq!(split_sequence(&bytes).unwrap(), vec![0..5, 5..9, 9..16]);
+    assert!(split_sequence(&bytes[..15]).is_err());
+}
+
+#[test]
+fn split_sequence_oom() {
+    let bad = vec![155u8, 0x00, 0xFF, 0xFF, 0xFF, 0x00, 0xFF, 0xFF, 0xFF];
+    assert!(split_sequence(&bad).is_err());
+    let bad = vec![0x5b, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF];
+    assert!(split_sequence(&bad).is_err());
+}
+
+#[test]
+fn roundtrip_enum_as_index() {
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    enum State { Idle, Running, Done }
+
+    let mut enc = Encoder::from_memory();
+    enc.set_unit_variants_as_index(true);
+    enc.encode(&[State::Idle, State::Running, State::Done]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0, 1, 2]);
+
+    let mut dec = Decoder::from_bytes(enc.as_bytes());
+    let states = dec.decode().collect::<Result<Vec<State>, _>>().unwrap();
+    assert_eq!(states, vec![State::Idle, State::Running, State::Done]);
+
+    let mut dec = Decoder::from_bytes(vec![3]);
+    assert!(dec.decode::<State>().next().unwrap().is_err());
+}
+
+#[test]
+fn deep_size_counts_byte_strings() {
+    let big = Cbor::Bytes(CborBytes(vec![0; 1 << 16]));
+    let mut map = HashMap::new();
+    map.insert("payload".to_owned(), Cbor::Array(vec![Cbor::Null, big]));
+    let doc = Cbor::Map(map);
+    assert!(doc.deep_size() >= 1 << 16);
+    assert!(Cbor::Null.deep_size() < doc.deep_size());
+}
+
+#[test]
+fn decode_fixed_size_byte_array() {
+    let key: Vec<u8> = (0..32).collect();
+    let bytes = encode(CborBytes(key.clone()));
+    let got: [u8; 32] = decode(&bytes);
+    assert_eq!(&got[..], &key[..]);
+
+    // An array of integers works too.
+    let got: [u8; 32] = decode(&encode(key.clone()));
+    assert_eq!(&got[..], &key[..]);
+
+    let mut dec = Decoder::from_bytes(encode(CborBytes(vec![0; 31])));
+    assert!(dec.decode::<[u8; 32]>().next().unwrap().is_err());
+}
+
+#[test]
+fn encoder_finish() {
+    let mut enc = Encoder::from_memory();
+    enc.encode(&[1, 2]).unwrap();
+    assert_eq!(enc.finish(), vec![1, 2]);
+
+    let mut enc = Encoder::from_writer(vec![]);
+    enc.encode(&["a"]).unwrap();
+    assert_eq!(enc.finish().unwrap(), vec![0x61, 0x61]);
+}
+
+#[test]
+fn regex_and_mime_tags() {
+    let bytes = encode(CborRegex("^a+$".to_owned()));
+    assert_eq!(bytes, b"\xd8\x23\x64^a+$");
+    round_trip(CborRegex("^a+$".to_owned()));
+
+    let msg = "Content-Type: text/plain\r\n\r\nhi".to_owned();
+    assert_eq!(&encode(CborMime(msg.clone()))[..2], &[0xd8, 0x24]);
+    round_trip(CborMime(msg));
+
+    // The wrong tag or a byte string is rejected.
+    let mut dec = Decoder::from_bytes(encode(CborMime("x".to_owned())));
+    assert!(dec.decode::<CborRegex>().next().unwrap().is_err());
+    let mut dec = Decoder::from_bytes(vec![0xd8, 0x23, 0x41, 0x61]);
+    assert!(dec.decode::<CborRegex>().next().unwrap().is_err());
+}
+
+#[test]
+fn items_with_raw() {
+    let mut enc = Encoder::from_memory();
+    enc.encode(&[vec!["a", "bc"], vec![]]).unwrap();
+    let bytes = enc.into_bytes();
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    let items = dec.items_with_raw().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(items.len(), 2);
+    let mut raw_all = vec![];
+    for &(ref raw, ref item) in &items {
+        assert_eq!(&readone(raw), item);
+        raw_all.extend_from_slice(raw);
+    }
+    assert_eq!(raw_all, bytes);
+}
+
+#[test]
+fn roundtrip_options_of_custom_types() {
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct Inner { x: u8, y: Option<u8> }
+
+    round_trip(Some(CborBytes(vec![1, 2, 3])));
+    round_trip(None::<CborBytes>);
+    round_trip(vec![Some(CborBytes(vec![])), None]);
+    round_trip(Some(CborRegex("a|b".to_owned())));
+    round_trip(Some(Inner { x: 1, y: None }));
+    round_trip(None::<Inner>);
+    assert_eq!(decode::<Option<CborBytes>>(&[0xf6]), None);
+    assert_eq!(decode::<Option<CborBytes>>(&[0x41, 0x07]),
+               Some(CborBytes(vec![7])));
+}
+
+#[test]
+fn encode_many_large_strings() {
+    // Text strings are copied straight through after their length prefix.
+    let s = ::std::iter::repeat("ünïcødé ").take(10_000).collect::<String>();
+    let items = vec![s.clone(); 100];
+    let bytes = encode(&items);
+    // Each string has a 5 byte header, and the array has a 2 byte header.
+    assert_eq!(bytes.len(), 2 + items.len() * (5 + s.len()));
+    assert_eq!(&bytes[2..7], &[0x7a, 0x00, 0x01, 0xd4, 0xc0]);
+    assert_eq!(decode::<Vec<String>>(&bytes), items);
+}
+
+#[test]
+fn encode_cbor_is_faithful() {
+    // Canonical encodings from appendix A of RFC 7049.
+    let vectors = [
+        "00", "17", "1818", "1903e8", "1a000f4240", "1b000000e8d4a51000",
+        "1bffffffffffffffff", "20", "29", "3863", "3903e7", "f90000",
+        "f98000", "f93c00", "fb3ff199999999999a", "f93e00", "f97bff",
+        "fa47c35000", "fa7f7fffff", "fb7e37e43c8800759c", "f90001",
+        "f90400", "f9c400", "fbc010666666666666", "f97c00", "f97e00",
+        "f9fc00", "f4", "f5", "f6", "f7", "c074323031332d30332d32315432303a         30343a30305a", "c11a514b67b0", "c1fb41d452d9ec200000",
+        "d74401020304", "d818456449455446", "40", "4401020304", "60",
+        "6161", "6449455446", "62225c", "62c3bc", "63e6b0b4",
+        "64f0908591", "80", "83010203", "8301820203820405",
+        "98190102030405060708090a0b0c0d0e0f101112131415161718181819",
+        "a0", "a26161016162820203", "826161a161626163",
+        "a56161614161626142616361436164614461656145",
+    ];
+    for hex in vectors.iter() {
+        let bytes = hex.replace(" ", "").from_hex().unwrap();
+        let mut enc = Encoder::from_memory();
+        enc.encode_cbor(&readone(&bytes)).unwrap();
+        assert_eq!(enc.as_bytes(), &bytes[..], "vector {}", hex);
+    }
+}
+
+#[test]
+fn encode_cbor_keeps_signed_widths() {
+    let vectors: &[&[u8]] = &[
+        // -20 in every width longer than one byte.
+        &[0x39, 0x00, 0x13],
+        &[0x3a, 0x00, 0x00, 0x00, 0x13],
+        &[0x3b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x13],
+        // Values that the decoder widens to the next signed type.
+        &[0x38, 0xff],
+        &[0x39, 0xff, 0xff],
+        &[0x3a, 0xff, 0xff, 0xff, 0xff],
+    ];
+    for bytes in vectors {
+        let mut enc = Encoder::from_memory();
+        enc.encode_cbor(&readone(bytes)).unwrap();
+        assert_eq!(enc.as_bytes(), *bytes);
+    }
+}
+
+#[test]
+fn decode_half_floats() {
+    assert_eq!(decode::<f32>(&[0xf9, 0x3c, 0x00]), 1.0);
+    assert_eq!(decode::<f32>(&[0xf9, 0x7b, 0xff]), 65504.0);
+    assert_eq!(decode::<f64>(&[0xf9, 0x00, 0x01]), 5.960464477539063e-8);
+    assert_eq!(decode::<f32>(&[0xf9, 0xc4, 0x00]), -4.0);
+    assert!(decode::<f32>(&[0xf9, 0x7e, 0x00]).is_nan());
+}
+
+#[test]
+fn collapse_repeated_tags() {
+    // 1(1(1(0))) and 1(2(0))
+    let bytes = vec![0xc1, 0xc1, 0xc1, 0x00, 0xc1, 0xc2, 0x00];
+    let mut dec = Decoder::from_bytes(bytes.clone());
+    dec.set_collapse_repeated_tags(true);
+    let items = dec.items().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(items, vec![readone(&[0xc1, 0x00]), readone(&bytes[4..])]);
+
+    let tripled = Decoder::from_bytes(bytes).items().next().unwrap();
+    assert_eq!(tripled.unwrap(), readone(&[0xc1, 0xc1, 0xc1, 0x00]));
+}
+
+#[test]
+fn max_string_len() {
+    // A 2 MiB byte string header, with no data behind it.
+    let mut dec = Decoder::from_bytes(vec![0x5a, 0x00, 0x20, 0x00, 0x00]);
+    dec.set_max_string_len(1 << 20);
+    let err = dec.items().next().unwrap().unwrap_err();
+    assert!(err.to_string().contains("maximum"), "{}", err);
+
+    let mut dec = Decoder::from_bytes(encode("abcd"));
+    dec.set_max_string_len(3);
+    assert!(dec.items().next().unwrap().is_err());
+
+    // (_ "ab", "cd")
+    let mut dec = Decoder::from_bytes(b"\x7f\x62ab\x62cd\xff".to_vec());
+    dec.set_max_string_len(3);
+    assert!(dec.items().next().unwrap().is_err());
+
+    let mut dec = Decoder::from_bytes(encode("abc"));
+    dec.set_max_string_len(3);
+    assert_eq!(dec.decode::<String>().next().unwrap().unwrap(), "abc");
+}
+
+#[test]
+fn as_integer() {
+    let max = readone(&[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(max.as_integer(), Some(CborInteger(::std::u64::MAX as i128)));
+    assert_eq!(readone(&[0x38, 0x63]).as_integer(), Some(CborInteger(-100)));
+    assert_eq!(readone(&[0x17]).as_integer(), Some(CborInteger(23)));
+    assert_eq!(readone(&[0xf9, 0x3c, 0x00]).as_integer(), None);
+    assert_eq!(readone(&[0x61, 0x31]).as_integer(), None);
+}
+
+#[test]
+fn encode_borrowed_cow_slice() {
+    use std::borrow::Cow;
+
+    // [1, "hi", null], with the 1 written in two bytes.
+    let bytes = vec![0x83, 0x19, 0x00, 0x01, 0x62, 0x68, 0x69, 0xf6];
+    let doc = readone(&bytes);
+    let items = match doc {
+        Cbor::Array(ref items) => Cow::Borrowed(&items[..]),
+        _ => unreachable!(),
+    };
+    // `Encodable` writes the smallest width.
+    assert_eq!(encode(&items), vec![0x83, 0x01, 0x62, 0x68, 0x69, 0xf6]);
+
+    // The faithful path works element by element on the borrowed slice.
+    let mut enc = Encoder::from_memory();
+    enc.write_array_header(items.len() as u64).unwrap();
+    for item in items.iter() {
+        enc.encode_cbor(item).unwrap();
+    }
+    assert_eq!(enc.as_bytes(), &bytes[..]);
+
+    let owned: Cow<[Cbor]> = match readone(&bytes) {
+        Cbor::Array(items) => Cow::Owned(items),
+        _ => unreachable!(),
+    };
+    assert_eq!(owned, items);
+}
+
+#[test]
+fn expect_array_and_map() {
+    let mut enc = Encoder::from_memory();
+    enc.encode(&[(1, 2, 3)]).unwrap();
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), 1);
+    enc.encode(&[map]).unwrap();
+    let bytes = enc.into_bytes();
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    assert_eq!(dec.expect_array(3).unwrap(), Some(3));
+    let nums = dec.decode().take(3).collect::<Result<Vec<u8>, _>>().unwrap();
+    assert_eq!(nums, vec![1, 2, 3]);
+    assert_eq!(dec.expect_map(1).unwrap(), Some(1));
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    assert!(dec.expect_array(2).is_err());
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    assert!(dec.expect_map(3).is_err());
+    let mut dec = Decoder::from_bytes(vec![0x9f, 0xff]);
+    assert_eq!(dec.expect_array(5).unwrap(), None);
+}
+
+#[test]
+fn huge_lengths_fail_cleanly() {
+    // An array, a map and a string, each claiming 2^63 - 1 entries (or
+    // bytes) with nothing behind them.
+    let huge = [0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff];
+    for &major in &[0x9b, 0xbb, 0x5b] {
+        let mut bytes = vec![major];
+        bytes.extend_from_slice(&huge);
+        // (The missing data is treated as the end of the stream.)
+        let mut dec = Decoder::from_bytes(bytes);
+        assert!(dec.items().next().map_or(true, |item| item.is_err()));
+    }
+
+    // Unsigned integers that don't fit in the signed type are rejected
+    // rather than wrapping around.
+    let max = encode(::std::u64::MAX);
+    assert!(Decoder::from_bytes(max).decode::<i64>().next().unwrap().is_err());
+    let big = encode(::std::u32::MAX);
+    assert!(Decoder::from_bytes(big).decode::<i32>().next().unwrap().is_err());
+    assert_eq!(decode::<i64>(&encode(::std::i64::MAX)), ::std::i64::MAX);
+}
+
+#[test]
+fn canonicalize() {
+    use cbor::{CborFloat, CborSigned, CborUnsigned};
+
+    fn doc(n: Cbor, neg: Cbor, f: Cbor) -> Cbor {
+        let mut map = HashMap::new();
+        map.insert("n".to_owned(), n);
+        map.insert("f".to_owned(), Cbor::Array(vec![f]));
+        Cbor::Array(vec![Cbor::Map(map), neg])
+    }
+    let mut wide = doc(Cbor::Unsigned(CborUnsigned::UInt64(1)),
+                       Cbor::Signed(CborSigned::Int32(-200)),
+                       Cbor::Float(CborFloat::Float64(1.5)));
+    let mut narrow = doc(Cbor::Unsigned(CborUnsigned::UInt8(1)),
+                         Cbor::Signed(CborSigned::Int16(-200)),
+                         Cbor::Float(CborFloat::Float16(1.5)));
+    assert!(wide != narrow);
+    wide.canonicalize();
+    narrow.canonicalize();
+    assert_eq!(wide, narrow);
+
+    let mut enc1 = Encoder::from_memory();
+    enc1.encode_cbor(&wide).unwrap();
+    let mut enc2 = Encoder::from_memory();
+    enc2.encode_cbor(&narrow).unwrap();
+    assert_eq!(enc1.as_bytes(), enc2.as_bytes());
+
+    let mut f = Cbor::Float(CborFloat::Float64(0.1));
+    f.canonicalize();
+    assert_eq!(f, Cbor::Float(CborFloat::Float64(0.1)));
+    let mut f = Cbor::Float(CborFloat::Float64(100000.0));
+    f.canonicalize();
+    assert_eq!(f, Cbor::Float(CborFloat::Float32(100000.0)));
+}
+
+#[test]
+fn uuid_tag() {
+    let bytes = "d825506ba7b8109dad11d180b400c04fd430c8".from_hex().unwrap();
+    let id: CborUuid = decode(&bytes);
+    assert_eq!(&id.0[..4], &[0x6b, 0xa7, 0xb8, 0x10]);
+    assert_eq!(encode(id), bytes);
+    round_trip(id);
+
+    let mut dec = Decoder::from_bytes(vec![0xd8, 0x25, 0x42, 0x00, 0x01]);
+    assert!(dec.decode::<CborUuid>().next().unwrap().is_err());
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn uuid_conversions() {
+    let id = uuid::Uuid::from_bytes([7; 16]);
+    assert_eq!(CborUuid::from(id), CborUuid([7; 16]));
+    assert_eq!(uuid::Uuid::from(CborUuid([7; 16])), id);
+}
+
+#[test]
+fn sort_map_keys() {
+    // A map that writes the same key twice, so that the keys tie.
+    struct Dupes;
+    impl Encodable for Dupes {
+        fn encode<E: rustc_serialize::Encoder>(&self, e: &mut E)
+                                              -> Result<(), E::Error> {
+            e.emit_map(3, |e| {
+                for (i, &(k, v)) in [("k", 2), ("k", 1), ("a", 3)]
+                                        .iter().enumerate() {
+                    try!(e.emit_map_elt_key(i, |e| k.encode(e)));
+                    try!(e.emit_map_elt_val(i, |e| v.encode(e)));
+                }
+                Ok(())
+            })
+        }
+    }
+
+    let mut enc = Encoder::from_memory();
+    enc.set_sort_map_keys(true);
+    enc.encode(&[Dupes]).unwrap();
+    assert_eq!(enc.as_bytes(), b"\xa3\x61a\x03\x61k\x01\x61k\x02");
+
+    let mut map = HashMap::new();
+    for k in &["bb", "c", "a", "aaa", "ab"] {
+        let mut inner = HashMap::new();
+        inner.insert(k.to_string(), 0);
+        inner.insert("z".to_owned(), 1);
+        map.insert(k.to_string(), inner);
+    }
+    let mut enc = Encoder::from_memory();
+    enc.set_sort_map_keys(true);
+    enc.encode(&[&map]).unwrap();
+    let bytes = enc.into_bytes();
+    assert_eq!(&bytes[..5], b"\xa5\x61a\xa2\x61");
+
+    // This is the same order that `encode_cbor` uses.
+    let mut enc = Encoder::from_memory();
+    enc.encode_cbor(&readone(&bytes)).unwrap();
+    assert_eq!(enc.as_bytes(), &bytes[..]);
+}
+
+#[test]
+fn events_doubly_tagged_integer() {
+    use cbor::{CborUnsigned, Event};
+
+    // 55799(1(1000)), then [_ "a"]
+    let bytes = vec![0xd9, 0xd9, 0xf7, 0xc1, 0x19, 0x03, 0xe8,
+                     0x9f, 0x61, 0x61, 0xff];
+    let mut dec = Decoder::from_bytes(bytes);
+    let events = dec.events().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(events, vec![
+        Event::Tag(55799),
+        Event::Tag(1),
+        Event::Unsigned(CborUnsigned::UInt16(1000)),
+        Event::ArrayStart(None),
+        Event::Unicode("a".to_owned()),
+        Event::Break,
+    ]);
+}
+
+#[test]
+fn decode_map_entries() {
+    let mut map = HashMap::new();
+    for (i, k) in ["a", "b", "c", "d"].iter().enumerate() {
+        map.insert(k.to_string(), i as i32 - 1);
+    }
+    let bytes = encode(&map);
+    let mut dec = Decoder::from_bytes(bytes);
+    let sum = dec.decode_map_entries::<String, i32>()
+                 .map(|entry| entry.unwrap().1)
+                 .fold(0, |sum, v| sum + v);
+    assert_eq!(sum, 2);
+
+    // {_ "a": 1, "b": -5}
+    let bytes = b"\xbf\x61a\x01\x61b\x24\xff".to_vec();
+    let mut dec = Decoder::from_bytes(bytes);
+    let entries = dec.decode_map_entries::<String, i32>()
+                     .collect::<Result<Vec<_>, _>>()
+                     .unwrap();
+    assert_eq!(entries, vec![("a".to_owned(), 1), ("b".to_owned(), -5)]);
+
+    let mut dec = Decoder::from_bytes(encode(vec![1]));
+    let mut entries = dec.decode_map_entries::<String, i32>();
+    assert!(entries.next().unwrap().is_err());
+    assert!(entries.next().is_none());
+}
+
+#[test]
+fn roundtrip_positional_struct() {
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct Reading { id: u8, note: Option<String>, value: i32 }
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct One { only: Option<u8> }
+
+    let v = Reading { id: 7, note: None, value: -2 };
+    let mut enc = Encoder::from_memory();
+    enc.set_structs_as_arrays(true);
+    enc.encode(&[&v]).unwrap();
+    enc.encode(&[One { only: None }]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x83, 0x07, 0xf6, 0x21, 0x81, 0xf6]);
+
+    let mut dec = Decoder::from_bytes(enc.as_bytes());
+    assert_eq!(dec.decode::<Reading>().next().unwrap().unwrap(), v);
+    assert_eq!(dec.decode::<One>().next().unwrap().unwrap(),
+               One { only: None });
+
+    // The map form is still accepted, and a missing position is an error.
+    round_trip(Reading { id: 1, note: Some("x".to_owned()), value: 3 });
+    let mut dec = Decoder::from_bytes(vec![0x82, 0x07, 0xf6]);
+    assert!(dec.decode::<Reading>().next().unwrap().is_err());
+}
+
+#[test]
+fn decode_at_offset() {
+    let mut enc = Encoder::from_memory();
+    enc.encode(&[vec![1, 2], vec![300], vec![]]).unwrap();
+    let bytes = enc.into_bytes();
+    let offsets: Vec<u64> = split_sequence(&bytes).unwrap()
+                                                   .iter()
+                                                   .map(|r| r.start as u64)
+                                                   .collect();
+
+    let mut dec = Decoder::from_seekable(::std::io::Cursor::new(bytes));
+    assert_eq!(dec.decode_at::<Vec<u32>>(offsets[1]).unwrap(), vec![300]);
+    assert_eq!(dec.decode_at::<Vec<u32>>(offsets[0]).unwrap(), vec![1, 2]);
+    // Reading continues after the item that was decoded last.
+    let rest = dec.decode().collect::<Result<Vec<Vec<u32>>, _>>().unwrap();
+    assert_eq!(rest, vec![vec![300], vec![]]);
+    assert!(dec.decode_at::<Vec<u32>>(1000).is_err());
+}
+
+#[test]
+fn reject_nonfinite_floats() {
+    for bytes in &[[0xf9, 0x7e, 0x00], [0xf9, 0x7c, 0x00]] {
+        let mut dec = Decoder::from_bytes(&bytes[..]);
+        assert!(!dec.decode::<f64>().next().unwrap().unwrap().is_finite());
+
+        let mut dec = Decoder::from_bytes(&bytes[..]);
+        dec.set_reject_nonfinite_floats(true);
+        assert!(dec.decode::<f64>().next().unwrap().is_err());
+    }
+    let mut dec = Decoder::from_bytes(vec![0xf9, 0x3c, 0x00]);
+    dec.set_reject_nonfinite_floats(true);
+    assert_eq!(dec.decode::<f64>().next().unwrap().unwrap(), 1.0);
+}
+
+#[test]
+fn strict_rfc8949() {
+    fn strict(bytes: &[u8]) -> bool {
+        let mut dec = Decoder::from_bytes(bytes).strict_rfc8949();
+        dec.items().next().unwrap().is_ok()
+    }
+    // {"a": 1, "bb": [-500, 1.5, NaN, 100000.0]}
+    assert!(strict(&[0xa2, 0x61, 0x61, 0x01, 0x62, 0x62, 0x62, 0x84,
+                     0x39, 0x01, 0xf3, 0xf9, 0x3e, 0x00, 0xf9, 0x7e, 0x00,
+                     0xfa, 0x47, 0xc3, 0x50, 0x00]));
+    // Duplicate key.
+    assert!(!strict(&[0xa2, 0x61, 0x61, 0x01, 0x61, 0x61, 0x02]));
+    // Keys out of order.
+    assert!(!strict(&[0xa2, 0x62, 0x62, 0x62, 0x01, 0x61, 0x61, 0x02]));
+    // Integers, lengths and tags that aren't as short as possible.
+    assert!(!strict(&[0x18, 0x17]));
+    assert!(!strict(&[0x39, 0x00, 0x01]));
+    assert!(!strict(&[0x98, 0x01, 0x01]));
+    assert!(!strict(&[0xd8, 0x01, 0x01]));
+    // 1.5 and NaN as single precision floats.
+    assert!(!strict(&[0xfa, 0x3f, 0xc0, 0x00, 0x00]));
+    assert!(!strict(&[0xfa, 0x7f, 0xc0, 0x00, 0x00]));
+    // Indefinite length.
+    assert!(!strict(&[0x9f, 0xff]));
+
+    let mut dec = Decoder::from_bytes(vec![0xa2, 0x61, 0x61, 0x01,
+                                           0x61, 0x61, 0x02]);
+    dec.set_reject_duplicate_keys(true);
+    assert!(dec.items().next().unwrap().is_err());
+    let mut dec = Decoder::from_bytes(vec![0x18, 0x17]);
+    dec.set_reject_non_canonical(true);
+    assert!(dec.items().next().unwrap().is_err());
+}
+
+#[test]
+fn drop_undefined_map_values() {
+    // {"a": 1, "b": undefined, "c": 31(undefined)}
+    let bytes = vec![0xa3, 0x61, 0x61, 0x01, 0x61, 0x62, 0xf7,
+                     0x61, 0x63, 0xd8, 0x1f, 0xf7];
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    match dec.items().next().unwrap().unwrap() {
+        Cbor::Map(map) => assert_eq!(map.len(), 3),
+        v => panic!("expected map, got {:?}", v),
+    }
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_drop_undefined_map_values(true);
+    let map: HashMap<String, u8> = dec.decode().next().unwrap().unwrap();
+    let mut want = HashMap::new();
+    want.insert("a".to_owned(), 1);
+    assert_eq!(map, want);
+}
+
+#[test]
+fn encode_bytes_stream() {
+    let mut enc = Encoder::from_memory();
+    {
+        let mut stream = enc.bytes_stream().unwrap();
+        stream.write_chunk(b"abc").unwrap();
+        stream.write_chunk(b"").unwrap();
+        stream.write_chunk(&[0; 30]).unwrap();
+        stream.finish().unwrap();
+    }
+    let mut want = b"abc".to_vec();
+    want.extend_from_slice(&[0; 30]);
+    let mut dec = Decoder::from_bytes(enc.as_bytes());
+    let got: CborBytes = dec.decode().next().unwrap().unwrap();
+    assert_eq!(got.0, want);
+    assert!(dec.decode::<CborBytes>().next().is_none());
+}
+
+#[test]
+fn value_conversions() {
+    use std::convert::TryFrom;
+    use cbor::Value;
+
+    assert_eq!(u64::try_from(Value::from(5u8)).unwrap(), 5);
+    assert_eq!(i64::try_from(Value::from(-5i32)).unwrap(), -5);
+    assert!(u64::try_from(Value::from(-5i32)).is_err());
+    assert!(String::try_from(Value::from(1.5)).is_err());
+
+    // [1.5, NaN, -Infinity, h'00ff', 1("x"), undefined]
+    let value = readone(&[0x86, 0xf9, 0x3e, 0x00, 0xf9, 0x7e, 0x00,
+                          0xf9, 0xfc, 0x00, 0x42, 0x00, 0xff,
+                          0xc1, 0x61, 0x78, 0xf7]);
+    assert_eq!(value.to_string(),
+               r#"[1.5, NaN, -Infinity, h'00ff', 1("x"), undefined]"#);
+}
+
+#[test]
+fn encode_cbor_deeply_nested() {
+    const DEPTH: usize = 50_000;
+    let mut v = Cbor::Array(vec![]);
+    for _ in 0..DEPTH {
+        v = Cbor::Array(vec![v]);
+    }
+    let mut enc = Encoder::from_memory();
+    enc.encode_cbor(&v).unwrap();
+    let mut want = vec![0x81; DEPTH];
+    want.push(0x80);
+    assert_eq!(enc.as_bytes(), &*want);
+
+    // Dropping the value normally would recurse, so take it apart first.
+    while let Cbor::Array(mut vals) = v {
+        match vals.pop() {
+            Some(inner) => v = inner,
+            None => break,
+        }
+    }
+}
+
+#[test]
+fn fixed_int_width() {
+    use cbor::IntWidth;
+
+    let mut enc = Encoder::from_memory();
+    enc.set_fixed_int_width(Some(IntWidth::Bits64));
+    enc.encode(&[0u8]).unwrap();
+    enc.encode(&[-1i8]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x1b, 0, 0, 0, 0, 0, 0, 0, 0,
+                                 0x3b, 0, 0, 0, 0, 0, 0, 0, 0]);
+
+    let mut enc = Encoder::from_memory();
+    enc.set_fixed_int_width(Some(IntWidth::Bits16));
+    enc.encode(&[vec![23u32]]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x81, 0x19, 0x00, 0x17]);
+    assert!(enc.encode(&[65536u32]).is_err());
+    assert!(enc.encode(&[-65537i32]).is_err());
+
+    let mut dec = Decoder::from_bytes(&[0x19, 0x00, 0x17][..]);
+    assert_eq!(dec.decode::<u32>().next().unwrap().unwrap(), 23);
+
+    // Variant indices aren't integer values, so they aren't padded.
+    #[derive(RustcEncodable)]
+    enum State { Idle, Running }
+
+    let mut enc = Encoder::from_memory();
+    enc.set_fixed_int_width(Some(IntWidth::Bits32));
+    enc.set_unit_variants_as_index(true);
+    enc.encode(&[(State::Running, 1u8)]).unwrap();
+    enc.encode(&[State::Idle]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x82, 0x01, 0x1a, 0, 0, 0, 1, 0x00]);
+}
+
+#[test]
+fn cells_are_transparent() {
+    use std::cell::{Cell, RefCell};
+
+    // `rustc-serialize` already forwards to the inner value for both.
+    let v = RefCell::new(vec![1, -2, 3]);
+    assert_eq!(encode(&v), encode(vec![1, -2, 3]));
+    assert!(round_trip(v));
+    let v = Cell::new(70000u32);
+    assert_eq!(encode(&v), encode(70000u32));
+    assert!(round_trip(v));
+}
+
+#[test]
+fn flatten_round_trip() {
+    use cbor::Value;
+
+    let mut user = HashMap::new();
+    user.insert("names".to_owned(),
+                Value::from(vec![Value::from("a"), Value::from("b")]));
+    user.insert("a.b\\c".to_owned(), Value::from(1));
+    user.insert("none".to_owned(), Value::Array(vec![]));
+    let mut doc = HashMap::new();
+    doc.insert("user".to_owned(), Value::from(user));
+    doc.insert("empty".to_owned(), Value::Map(HashMap::new()));
+    doc.insert("ok".to_owned(), Value::from(true));
+    let doc = Value::from(doc);
+
+    let flat = doc.flatten();
+    let mut keys: Vec<&str> = flat.keys().map(|k| &**k).collect();
+    keys.sort();
+    assert_eq!(keys, vec!["empty", "ok", "user.a\\.b\\\\c",
+                          "user.names.0", "user.names.1", "user.none"]);
+    assert_eq!(flat["user.names.1"], Value::from("b"));
+    assert_eq!(Value::unflatten(flat), doc);
+
+    assert_eq!(Value::unflatten(Value::from(5).flatten()), Value::from(5));
+}
+
+#[cfg(feature = "quickcheck")]
+#[test]
+fn prop_arbitrary_cbor_round_trip() {
+    fn prop(v: Cbor) -> bool {
+        let mut enc = Encoder::from_memory();
+        enc.encode_cbor(&v).unwrap();
+        readone(enc.as_bytes()) == v
+    }
+    QuickCheck::new().quickcheck(prop as fn(Cbor) -> bool)
+}
+
+#[test]
+fn simple_value_map() {
+    use cbor::SimpleValues;
+
+    let map = SimpleValues { false_value: 0, true_value: 1, null_value: 64 };
+    type Data = (bool, bool, Option<u8>, Option<u8>);
+    let data: Data = (true, false, None, Some(5));
+    let mut enc = Encoder::from_memory();
+    enc.set_simple_value_map(map);
+    enc.encode(&[data]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x84, 0xe1, 0xe0, 0xf8, 0x40, 0x05]);
+
+    let mut dec = Decoder::from_bytes(enc.as_bytes());
+    assert!(dec.decode::<Data>().next().unwrap().is_err());
+    let mut dec = Decoder::from_bytes(enc.as_bytes());
+    dec.set_simple_value_map(map);
+    assert_eq!(dec.decode::<Data>().next().unwrap().unwrap(), data);
+
+    let mut enc = Encoder::from_memory();
+    enc.set_simple_value_map(SimpleValues { null_value: 24, ..map });
+    assert!(enc.encode(&[()]).is_err());
+
+    // The standard simple values can be rearranged too.
+    let swapped = SimpleValues { false_value: 21, true_value: 20, ..map };
+    let mut enc = Encoder::from_memory();
+    enc.set_simple_value_map(swapped);
+    enc.encode(&[data]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x84, 0xf4, 0xf5, 0xf8, 0x40, 0x05]);
+    let mut dec = Decoder::from_bytes(enc.as_bytes());
+    dec.set_simple_value_map(swapped);
+    assert_eq!(dec.decode::<Data>().next().unwrap().unwrap(), data);
+}
+
+#[test]
+fn decode_length_prefixed() {
+    let data = ("frame".to_owned(), vec![1u32, 2, 3]);
+    let body = encode(&data);
+    let mut framed = vec![0, 0, 0, body.len() as u8];
+    framed.extend_from_slice(&body);
+    framed.extend_from_slice(&[0, 0, 0, 1, 0xf5]);
+
+    let mut rdr = &framed[..];
+    let got: (String, Vec<u32>) = Decoder::decode_length_prefixed(&mut rdr)
+                                          .unwrap();
+    assert_eq!(got, data);
+    let got: bool = Decoder::decode_length_prefixed(&mut rdr).unwrap();
+    assert!(got);
+
+    // Any reader works, and nothing past the frame is consumed.
+    let mut rdr = ::std::io::Cursor::new(framed.clone());
+    let got: (String, Vec<u32>) = Decoder::decode_length_prefixed(&mut rdr)
+                                          .unwrap();
+    assert_eq!(got, data);
+    assert_eq!(rdr.position() as usize, 4 + body.len());
+
+    // Truncated frames and frames with trailing bytes.
+    let mut rdr = &framed[..body.len()];
+    assert!(Decoder::decode_length_prefixed::<(String, Vec<u32>)>(&mut rdr)
+                    .is_err());
+    let mut rdr = &[0, 0, 0, 2, 0xf5, 0xf5][..];
+    assert!(Decoder::decode_length_prefixed::<bool>(&mut rdr).is_err());
+}
+
+#[test]
+fn nested_map_order_is_deterministic() {
+    // `Cbor` maps are unordered, but they are always written in canonical
+    // key order, whatever order they were built or decoded in.
+    let keys = ["zz", "a", "m", "bbb", "c"];
+    let build = |order: &[usize]| {
+        let mut inner = HashMap::new();
+        let mut outer = HashMap::new();
+        for &i in order {
+            inner.insert(keys[i].to_owned(), Cbor::Bool(true));
+        }
+        for &i in order.iter().rev() {
+            outer.insert(keys[i].to_owned(), Cbor::Map(inner.clone()));
+        }
+        Cbor::Map(outer)
+    };
+    let (a, b) = (build(&[0, 1, 2, 3, 4]), build(&[4, 2, 0, 3, 1]));
+
+    let bytes = |v: &Cbor| {
+        let mut enc = Encoder::from_memory();
+        enc.encode_cbor(v).unwrap();
+        enc.into_bytes()
+    };
+    assert_eq!(bytes(&a), bytes(&b));
+    assert_eq!(a.to_string(), b.to_string());
+    assert!(a.to_string().starts_with(r#"{"a": {"a": true, "bbb": true"#));
+    assert_eq!(bytes(&a)[..4], [0xa5, 0x61, 0x61, 0xa5]);
+
+    // `sort_keys` gives the same order at every level, without changing any
+    // widths.
+    let order: Vec<&str> = a.sort_keys().iter().map(|e| &e.0[..]).collect();
+    assert_eq!(order, vec!["a", "c", "m", "zz", "bbb"]);
+    for (_, inner) in a.sort_keys().into_iter().chain(b.sort_keys()) {
+        let inner: Vec<&str> =
+            inner.sort_keys().iter().map(|e| &e.0[..]).collect();
+        assert_eq!(inner, order);
+    }
+    let wide = Cbor::Unsigned(cbor::CborUnsigned::UInt64(1));
+    let mut map = HashMap::new();
+    map.insert("k".to_owned(), wide.clone());
+    assert_eq!(Cbor::Map(map).sort_keys()[0].1, &wide);
+    assert!(Cbor::Null.sort_keys().is_empty());
+}
+
+#[test]
+fn encode_borrowed_subtree() {
+    // {"id": 7, "payload": [1, {"k": h'ff'}]}
+    let doc = readone(&[0xa2, 0x62, 0x69, 0x64, 0x07,
+                        0x67, 0x70, 0x61, 0x79, 0x6c, 0x6f, 0x61, 0x64,
+                        0x82, 0x01, 0xa1, 0x61, 0x6b, 0x41, 0xff]);
+    let payload = &doc["payload"];
+
+    let mut enc = Encoder::from_memory();
+    enc.encode_cbor(payload).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x82, 0x01, 0xa1, 0x61, 0x6b, 0x41, 0xff]);
+
+    let mut enc = Encoder::from_memory();
+    enc.encode(&[payload]).unwrap();
+    assert_eq!(enc.as_bytes(), &[0x82, 0x01, 0xa1, 0x61, 0x6b, 0x41, 0xff]);
+}
+
+#[cfg(feature = "chrono")]
+#[test]
+fn chrono_datetime_tags() {
+    use chrono::{TimeZone, Utc};
+    use cbor::{CborDateTime, CborEpochDateTime};
+
+    let time = Utc.timestamp_opt(1363896240, 500_000_000).unwrap();
+    let string = encode(CborDateTime(time));
+    assert_eq!(&string[3..], &b"2013-03-21T20:04:00.500Z"[..]);
+    let epoch = encode(CborEpochDateTime(time));
+    assert_eq!(epoch, &[0xc1, 0xfb, 0x41, 0xd4, 0x52, 0xd9, 0xec, 0x20,
+                        0x00, 0x00]);
+    for bytes in &[string, epoch] {
+        let got: CborDateTime = decode(bytes);
+        assert_eq!(got.0, time);
+        let got: CborEpochDateTime = decode(bytes);
+        assert_eq!(got.0, time);
+    }
+
+    // Offsets are converted to UTC.
+    let mut bytes = vec![0xc0, 0x78, 0x19];
+    bytes.extend_from_slice(b"2013-03-21T22:04:00+02:00");
+    let got: CborDateTime = decode(&bytes);
+    assert_eq!(got.0, Utc.timestamp_opt(1363896240, 0).unwrap());
+    let before: CborEpochDateTime = decode(&[0xc1, 0xf9, 0xbe, 0x00]);
+    assert_eq!(before.0, Utc.timestamp_opt(-2, 500_000_000).unwrap());
+}
+
+#[test]
+fn accept_array_as_map() {
+    let mut want = HashMap::new();
+    want.insert("a".to_owned(), 1);
+    want.insert("b".to_owned(), 2);
+    let map = encode(&want);
+    let pairs = encode(vec![("a", 1), ("b", 2)]);
+
+    let mut dec = Decoder::from_bytes(&pairs[..]);
+    assert!(dec.decode::<HashMap<String, i32>>().next().unwrap().is_err());
+    for bytes in &[map, pairs] {
+        let mut dec = Decoder::from_bytes(&bytes[..]);
+        dec.set_accept_array_as_map(true);
+        let got: HashMap<String, i32> = dec.decode().next().unwrap().unwrap();
+        assert_eq!(got, want);
+    }
+
+    // Keys don't have to be strings.
+    let mut dec = Decoder::from_bytes(encode(vec![(7u8, true)]));
+    dec.set_accept_array_as_map(true);
+    let got: HashMap<u8, bool> = dec.decode().next().unwrap().unwrap();
+    assert_eq!(got.get(&7), Some(&true));
+
+    let mut dec = Decoder::from_bytes(encode(vec![vec![1, 2, 3]]));
+    dec.set_accept_array_as_map(true);
+    assert!(dec.decode::<HashMap<u8, u8>>().next().unwrap().is_err());
+}
+
+#[test]
+fn float_keys_as_pairs() {
+    use rustc_serialize::{Decoder as RustcDecoder, Encoder as RustcEncoder};
+
+    // A float map key, compared by its bits. NaN is rejected when decoding.
+    #[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+    struct FloatKey(u64);
+
+    impl Encodable for FloatKey {
+        fn encode<E: RustcEncoder>(&self, e: &mut E) -> Result<(), E::Error> {
+            e.emit_f64(f64::from_bits(self.0))
+        }
+    }
+
+    impl Decodable for FloatKey {
+        fn decode<D: RustcDecoder>(d: &mut D) -> Result<FloatKey, D::Error> {
+            let f = try!(d.read_f64());
+            if f.is_nan() {
+                return Err(d.error("NaN can't be a map key."));
+            }
+            Ok(FloatKey(f.to_bits()))
+        }
+    }
+
+    let mut grid = HashMap::new();
+    grid.insert(FloatKey(1.5f64.to_bits()), "a".to_owned());
+    grid.insert(FloatKey((-0.25f64).to_bits()), "b".to_owned());
+    let pairs: Vec<_> = grid.iter().collect();
+    let mut dec = Decoder::from_bytes(encode(pairs));
+    dec.set_accept_array_as_map(true);
+    let got: HashMap<FloatKey, String> =
+        dec.decode().next().unwrap().unwrap();
+    assert_eq!(got, grid);
+
+    let nan = vec![(::std::f64::NAN, "c")];
+    let mut dec = Decoder::from_bytes(encode(nan));
+    dec.set_accept_array_as_map(true);
+    assert!(dec.decode::<HashMap<FloatKey, String>>().next().unwrap()
+               .is_err());
+}
+
+#[test]
+fn bytes_eq_canonical() {
+    use cbor::bytes_eq_canonical;
+
+    // {"a": 1(h'01'), "bb": [-1, NaN]}, with the entries in either order,
+    // definite or indefinite lengths and different widths.
+    let a = [0xa2, 0x61, 0x61, 0xc1, 0x41, 0x01,
+             0x62, 0x62, 0x62, 0x82, 0x20, 0xf9, 0x7e, 0x00];
+    let b = [0xbf, 0x62, 0x62, 0x62, 0x9f, 0x38, 0x00,
+             0xfb, 0x7f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff,
+             0x61, 0x61, 0xd8, 0x01, 0x5f, 0x41, 0x01, 0xff, 0xff];
+    assert!(bytes_eq_canonical(&a, &b).unwrap());
+
+    // A different tag number, byte string and key.
+    let mut c = a;
+    c[3] = 0xc2;
+    assert!(!bytes_eq_canonical(&a, &c).unwrap());
+    let mut c = a;
+    c[5] = 0x02;
+    assert!(!bytes_eq_canonical(&a, &c).unwrap());
+    let mut c = a;
+    c[2] = 0x63;
+    assert!(!bytes_eq_canonical(&a, &c).unwrap());
+    // An extra data item, and a truncated one.
+    assert!(!bytes_eq_canonical(&[0x01], &[0x01, 0x01]).unwrap());
+    assert!(bytes_eq_canonical(&a, &a[..5]).is_err());
+}
+
+#[test]
+fn datetime_mode() {
+    use cbor::DateTimeMode;
+
+    fn check(s: &str, mode: DateTimeMode) -> Option<String> {
+        let mut dec = Decoder::from_bytes(encode(CborTagEncode::new(0, &s)));
+        dec.set_datetime_mode(mode);
+        let v = match dec.items().next().unwrap() {
+            Ok(Cbor::Tag(tag)) => tag.data,
+            Ok(v) => panic!("expected tag, got {:?}", v),
+            Err(_) => return None,
+        };
+        match *v {
+            Cbor::Unicode(ref s) => Some(s.clone()),
+            ref v => panic!("expected string, got {:?}", v),
+        }
+    }
+    let (strict, lenient) = (DateTimeMode::Strict, DateTimeMode::Lenient);
+
+    for s in &["2013-03-21T20:04:00Z", "2013-03-21T21:04:00.123456-01:00",
+               "2016-12-31T23:59:60Z"] {
+        assert_eq!(check(s, strict), Some(s.to_string()));
+        assert_eq!(check(s, lenient), Some(s.to_string()));
+    }
+    for &(s, want) in &[("2013-03-21t20:04:00z", "2013-03-21T20:04:00Z"),
+                        ("2013-03-21 20:04:00.5", "2013-03-21T20:04:00.5Z"),
+                        ("2013-03-21T20:04:00+0130",
+                         "2013-03-21T20:04:00+01:30")] {
+        assert_eq!(check(s, strict), None);
+        assert_eq!(check(s, lenient), Some(want.to_owned()));
+    }
+    for s in &["2013-02-29T20:04:00Z", "2013-03-21T24:00:00Z",
+               "2013-03-21T20:04:00.Z", "2013-03-21", "1363896240",
+               "2013-03-21T20:04:00Z junk"] {
+        assert_eq!(check(s, strict), None);
+        assert_eq!(check(s, lenient), None);
+        assert_eq!(check(s, DateTimeMode::Unchecked), Some(s.to_string()));
+    }
+
+    let mut dec = Decoder::from_bytes(vec![0xc0, 0x01]);
+    dec.set_datetime_mode(strict);
+    assert!(dec.items().next().unwrap().is_err());
+}
+
+#[test]
+fn encode_sequence() {
+    let mut enc = Encoder::from_memory();
+    enc.encode(vec![1u32, 500, 70000]).unwrap();
+    enc.encode(&["end"]).unwrap();
+    let bytes = enc.into_bytes();
+    assert_eq!(&bytes[..4], &[0x01, 0x19, 0x01, 0xf4]);
+
+    let mut dec = Decoder::from_bytes(bytes);
+    let nums: Vec<u32> = dec.decode().take(3)
+                            .collect::<Result<_, _>>().unwrap();
+    assert_eq!(nums, vec![1, 500, 70000]);
+    let rest: Vec<String> = dec.decode().collect::<Result<_, _>>().unwrap();
+    assert_eq!(rest, vec!["end".to_owned()]);
+}
+
+#[test]
+fn encode_seq() {
+    let mut enc = Encoder::from_memory();
+    enc.encode_seq(vec![1u32, 500, 70000]).unwrap();
+    enc.encode_seq(Some("end")).unwrap();
+
+    let mut dec = Decoder::from_bytes(enc.into_bytes());
+    let mut items = dec.items();
+    for &n in &[1u64, 500, 70000] {
+        assert_eq!(items.next().unwrap().unwrap().as_u64(), Some(n));
+    }
+    assert_eq!(items.next().unwrap().unwrap().as_str(), Some("end"));
+    assert!(items.next().is_none());
+}
+
+#[test]
+fn decode_vec_partial() {
+    use cbor::CborError;
+
+    let bytes = encode(vec!["one", "two", "three", "four", "five"]);
+    // Cut off in the middle of the third element.
+    let truncated = &bytes[..1 + 4 + 4 + 3];
+    let mut dec = Decoder::from_bytes(truncated);
+    let (vals, err) = dec.decode_vec_partial::<String>();
+    assert_eq!(vals, vec!["one".to_owned(), "two".to_owned()]);
+    match err {
+        Some(CborError::UnexpectedEOF) => {}
+        err => panic!("expected EOF, got {:?}", err),
+    }
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    let (vals, err) = dec.decode_vec_partial::<String>();
+    assert_eq!(vals.len(), 5);
+    assert!(err.is_none());
+
+    // A bad element stops decoding too.
+    let mut dec = Decoder::from_bytes(encode((1, 2, "x", 4)));
+    let (vals, err) = dec.decode_vec_partial::<u8>();
+    assert_eq!(vals, vec![1, 2]);
+    assert!(err.is_some());
+}
+
+#[test]
+fn atomic_round_trip() {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use cbor::{CborAtomicU64, CborAtomicUsize};
+
+    let counters = vec![
+        CborAtomicU64::new(0),
+        CborAtomicU64::new(1 << 40),
+        CborAtomicU64::from(AtomicU64::new(::std::u64::MAX)),
+    ];
+    let bytes = encode(&counters);
+    assert_eq!(bytes, encode(vec![0, 1u64 << 40, ::std::u64::MAX]));
+    let decoded: Vec<CborAtomicU64> = decode(&bytes);
+    let vals: Vec<u64> =
+        decoded.iter().map(|v| v.0.load(Ordering::SeqCst)).collect();
+    assert_eq!(vals, vec![0, 1 << 40, ::std::u64::MAX]);
+
+    let n: CborAtomicUsize = decode(&encode(7usize));
+    assert_eq!(n.0.load(Ordering::SeqCst), 7);
+}
+
+#[test]
+fn max_total_tags() {
+    // [1(1), 1(1(2)), 3]
+    let bytes = &[0x83, 0xc1, 0x01, 0xc1, 0xc1, 0x02, 0x03];
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_max_total_tags(3);
+    assert!(dec.items().next().unwrap().is_ok());
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_max_total_tags(2);
+    assert!(dec.items().next().unwrap().is_err());
+
+    // The count isn't reset between data items.
+    let mut dec = Decoder::from_bytes(vec![0xc1, 0x01, 0xc1, 0x02]);
+    dec.set_max_total_tags(1);
+    let mut items = dec.items();
+    assert!(items.next().unwrap().is_ok());
+    assert!(items.next().unwrap().is_err());
+}
+
+#[test]
+fn read_tagged_struct() {
+    use rustc_serialize::{Decoder as RustcDecoder, Encoder as RustcEncoder};
+    use cbor::read_tagged;
+
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct ErrorInfo {
+        code: i64,
+        message: String,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct RpcError(ErrorInfo);
+
+    impl Encodable for RpcError {
+        fn encode<E: RustcEncoder>(&self, e: &mut E) -> Result<(), E::Error> {
+            CborTagEncode::new(1001, &self.0).encode(e)
+        }
+    }
+
+    impl Decodable for RpcError {
+        fn decode<D: RustcDecoder>(d: &mut D) -> Result<RpcError, D::Error> {
+            read_tagged(d, 1001, Decodable::decode).map(RpcError)
+        }
+    }
+
+    let err = RpcError(ErrorInfo { code: -32601, message: "nope".into() });
+    let bytes = encode(&err);
+    assert_eq!(&bytes[..4], &[0xd9, 0x03, 0xe9, 0xa2]);
+    assert_eq!(decode::<RpcError>(&bytes), err);
+
+    let other = encode(CborTagEncode::new(1002, &err.0));
+    let mut dec = Decoder::from_bytes(other);
+    assert!(dec.decode::<RpcError>().next().unwrap().is_err());
+}
+
+#[test]
+fn debug_dump() {
+    // {"a": [_ 1, -500]}, 1(1.5)
+    let bytes = &[
+        0xa1, 0x61, 0x61, 0x9f, 0x01, 0x39, 0x01, 0xf3, 0xff,
+        0xc1, 0xf9, 0x3e, 0x00,
+    ];
+    assert_eq!(cbor::debug_dump(bytes).unwrap(), "\
+a1              # map(1)
+   61 61        # \"a\"
+   9f           # array(*)
+      01        # 1
+      39 01 f3  # -500
+   ff           # break
+c1              # tag(1)
+   f9 3e 00     # 1.5
+");
+    assert!(cbor::debug_dump(&bytes[..8]).is_err());
+}
+
+#[test]
+fn weak_references() {
+    use std::rc::Rc;
+    use std::sync::Arc;
+    use cbor::{CborArcWeak, CborRcWeak};
+
+    let live = Rc::new("alive".to_owned());
+    let dropped = Rc::new("gone".to_owned());
+    let refs = vec![
+        CborRcWeak(Rc::downgrade(&live)),
+        CborRcWeak(Rc::downgrade(&dropped)),
+    ];
+    drop(dropped);
+    let bytes = encode(&refs);
+    assert_eq!(bytes, encode(vec![Some("alive"), None]));
+
+    // Nothing owns the decoded values, so the references are dangling.
+    let decoded: Vec<CborRcWeak<String>> = decode(&bytes);
+    assert_eq!(decoded.len(), 2);
+    assert!(decoded.iter().all(|v| v.0.upgrade().is_none()));
+    let mut dec = Decoder::from_bytes(encode(1));
+    assert!(dec.decode::<CborRcWeak<String>>().next().unwrap().is_err());
+
+    let shared = Arc::new(5u8);
+    assert_eq!(encode(CborArcWeak(Arc::downgrade(&shared))), encode(5u8));
+    assert_eq!(encode(CborArcWeak::<u8>::new()), encode(()));
+}
+
+#[test]
+fn max_map_entries() {
+    // {_ "a": 1, "b": 2, "c": 3}
+    let indefinite = &[
+        0xbf, 0x61, 0x61, 0x01, 0x61, 0x62, 0x02, 0x61, 0x63, 0x03, 0xff,
+    ];
+    let mut map = HashMap::new();
+    map.insert("a", 1);
+    let definite = encode(map);
+
+    let mut dec = Decoder::from_bytes(&indefinite[..]);
+    dec.set_max_map_entries(3);
+    assert!(dec.items().next().unwrap().is_ok());
+
+    let mut dec = Decoder::from_bytes(&indefinite[..]);
+    dec.set_max_map_entries(2);
+    assert!(dec.items().next().unwrap().is_err());
+
+    let mut dec = Decoder::from_bytes(&definite[..]);
+    dec.set_max_map_entries(0);
+    assert!(dec.items().next().unwrap().is_err());
+
+    // Arrays aren't limited.
+    let mut dec = Decoder::from_bytes(encode(vec![1, 2, 3]));
+    dec.set_max_map_entries(0);
+    assert!(dec.items().next().unwrap().is_ok());
+}
+
+#[test]
+fn optional_floats() {
+    let widths: &[&[u8]] = &[
+        &[0xf9, 0x3e, 0x00],
+        &[0xfa, 0x3f, 0xc0, 0x00, 0x00],
+        &[0xfb, 0x3f, 0xf8, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+    ];
+    for bytes in widths {
+        let mut dec = Decoder::from_bytes(*bytes);
+        dec.set_strict_floats(true);
+        let v = dec.decode::<Option<f64>>().next().unwrap().unwrap();
+        assert_eq!(v, Some(1.5));
+    }
+    assert_eq!(decode::<Option<f64>>(&encode(Some(1.5f64))), Some(1.5));
+    assert_eq!(decode::<Option<f64>>(&encode(Some(1.5f32))), Some(1.5));
+    assert_eq!(encode(None::<f64>), vec![0xf6]);
+    assert_eq!(decode::<Option<f64>>(&[0xf6]), None);
+
+    // Integers are only converted when not strict, which is the default
+    // for compatibility.
+    assert_eq!(decode::<Option<f64>>(&encode(2)), Some(2.0));
+    let mut dec = Decoder::from_bytes(encode(-2));
+    dec.set_strict_floats(true);
+    assert!(dec.decode::<Option<f64>>().next().unwrap().is_err());
+    let mut dec = Decoder::from_bytes(encode(2));
+    dec.set_strict_floats(true);
+    assert!(dec.decode::<f32>().next().unwrap().is_err());
+}
+
+#[test]
+fn strict_floats_epoch_time() {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    let times = [
+        UNIX_EPOCH + Duration::from_secs(1363896240),
+        UNIX_EPOCH + Duration::from_millis(1363896240500),
+        UNIX_EPOCH - Duration::from_secs(10),
+    ];
+    for &time in times.iter() {
+        let mut dec = Decoder::from_bytes(encode(CborTime(time)));
+        dec.set_strict_floats(true);
+        let got = dec.decode::<CborTime>().next().unwrap().unwrap();
+        assert_eq!(got, CborTime(time));
+    }
+    // Only the content of tag 1 is exempt.
+    let mut dec = Decoder::from_bytes(encode((CborTime(times[0]), 2)));
+    dec.set_strict_floats(true);
+    assert!(dec.decode::<(CborTime, f64)>().next().unwrap().is_err());
+}
+
+#[test]
+fn into_iter_entries() {
+    use cbor::Value;
+
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), Value::from("x"));
+    map.insert("b".to_owned(), Value::from(vec![Value::from("y")]));
+    let upper: HashMap<String, Value> = Value::from(map)
+        .into_iter_entries()
+        .map(|(k, v)| (k.to_uppercase(), v))
+        .collect();
+    assert_eq!(upper.len(), 2);
+    assert_eq!(upper["A"], Value::from("x"));
+    assert_eq!(upper["B"][0], Value::from("y"));
+
+    assert_eq!(Value::from(vec![Value::from(1)]).into_iter_entries().count(),
+               0);
+}
+
+#[test]
+fn control_flow_round_trip() {
+    use std::ops::ControlFlow;
+    use cbor::CborControlFlow;
+
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    enum Flow {
+        Continue(String),
+        Break(u32),
+    }
+
+    let flows: Vec<CborControlFlow<u32, String>> = vec![
+        CborControlFlow(ControlFlow::Continue("more".to_owned())),
+        CborControlFlow(ControlFlow::Break(7)),
+    ];
+    let bytes = encode(&flows);
+    // The same as a derived enum.
+    assert_eq!(bytes, encode(vec![Flow::Continue("more".to_owned()),
+                                  Flow::Break(7)]));
+    assert_eq!(decode::<Vec<CborControlFlow<u32, String>>>(&bytes), flows);
+    assert_eq!(decode::<Vec<Flow>>(&bytes)[1], Flow::Break(7));
+}
+
+#[test]
+fn diag_float_options() {
+    use cbor::{DiagOptions, Value};
+
+    let v = Value::from(vec![
+        Value::from(1.0f64), Value::from(1.5f32), Value::from(-1e-7),
+        Value::from(1e20), Value::from(::std::f64::NAN),
+    ]);
+    assert_eq!(v.to_diag_with(&DiagOptions::default()), v.to_string());
+
+    let fixed = DiagOptions { float_precision: Some(2), no_exponent: false };
+    assert_eq!(v.to_diag_with(&fixed),
+               "[1.00, 1.50, -0.00, 100000000000000000000.00, NaN]");
+
+    let plain = DiagOptions { float_precision: None, no_exponent: true };
+    assert_eq!(v.to_diag_with(&plain),
+               "[1.0, 1.5, -0.0000001, 100000000000000000000.0, NaN]");
+
+    let nested = Value::from(vec![Value::from(vec![Value::from(0.125)])]);
+    let zero = DiagOptions { float_precision: Some(0), no_exponent: false };
+    assert_eq!(nested.to_diag_with(&zero), "[[0]]");
+}
+
+#[cfg(feature = "bytes")]
+#[test]
+fn shared_bytes() {
+    use bytes::Bytes;
+    use cbor::CborSharedBytes;
+
+    let data = vec![
+        CborSharedBytes(Bytes::from(vec![1, 2, 3])),
+        CborSharedBytes(Bytes::new()),
+    ];
+    let bytes = encode(&data);
+    assert_eq!(bytes, encode(vec![CborBytes(vec![1, 2, 3]),
+                                  CborBytes(vec![])]));
+    assert_eq!(decode::<Vec<CborSharedBytes>>(&bytes), data);
+
+    // The byte strings share the buffer they're read from.
+    let buf = Bytes::from(bytes);
+    let mut dec = Decoder::from_shared_bytes(buf.clone());
+    assert_eq!(dec.expect_array(2).unwrap(), Some(2));
+    let first = dec.read_shared_bytes().unwrap();
+    assert_eq!(&first[..], &[1, 2, 3]);
+    assert_eq!(first.as_ptr(), buf[2..].as_ptr());
+    assert!(dec.read_shared_bytes().unwrap().is_empty());
+
+    // Indefinite length byte strings are joined.
+    let mut dec = Decoder::from_shared_bytes(
+        Bytes::from(vec![0x5f, 0x41, 0x01, 0x41, 0x02, 0xff]));
+    assert_eq!(&dec.read_shared_bytes().unwrap()[..], &[1, 2]);
+
+    let mut dec = Decoder::from_shared_bytes(Bytes::from(vec![0x43, 0x01]));
+    assert!(dec.read_shared_bytes().is_err());
+    let mut dec = Decoder::from_shared_bytes(Bytes::from(encode("a")));
+    assert!(dec.read_shared_bytes().is_err());
+}
+
+#[test]
+fn tag_over_struct() {
+    use cbor::CborTag;
+
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    struct Point {
+        x: i32,
+        y: i32,
+    }
+
+    let point = Point { x: 3, y: -4 };
+    let tag = CborTag::new(40_000, readone(&encode(&point)));
+    let bytes = encode(Cbor::Tag(tag.clone()));
+    assert_eq!(readone(&bytes),
+               readone(&encode(CborTagEncode::new(40_000, &point))));
+
+    match readone(&bytes) {
+        Cbor::Tag(decoded) => {
+            assert_eq!(decoded, tag);
+            assert_eq!(decoded.decode::<Point>().unwrap(), point);
+            assert!(decoded.decode::<Vec<i32>>().is_err());
+        }
+        v => panic!("expected a tag, got {:?}", v),
+    }
+}
+
+#[test]
+fn skip_leading_junk() {
+    let mut bytes = vec![0xef, 0xbb, 0xbf, b'\n', b' '];
+    bytes.extend(encode(vec![1, 2]));
+    bytes.extend(encode(10));
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_skip_leading_junk(true);
+    let first: Vec<u8> = dec.decode().next().unwrap().unwrap();
+    assert_eq!(first, vec![1, 2]);
+    // Only leading junk is skipped, so the `10` later on (a newline byte) is
+    // decoded as usual.
+    assert_eq!(dec.decode::<u8>().next().unwrap().unwrap(), 10);
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    assert!(dec.decode::<Vec<u8>>().next().unwrap().is_err());
+
+    let mut dec = Decoder::from_bytes(&[0xef, 0xbb, 0x00][..]);
+    dec.set_skip_leading_junk(true);
+    assert!(dec.items().next().unwrap().is_err());
+
+    let mut dec = Decoder::from_bytes(&[0x00, 0x00, 0x81, 0x01][..]);
+    dec.set_skip_leading_junk(true);
+    dec.set_leading_junk_bytes(vec![0x00]);
+    assert_eq!(dec.decode::<Vec<u8>>().next().unwrap().unwrap(), vec![1]);
+}
+
+#[test]
+fn approx_eq() {
+    use cbor::{CborTag, Value};
+
+    fn doc(x: f64, y: f32) -> Value {
+        let mut map = HashMap::new();
+        map.insert("x".to_owned(), Value::from(x));
+        map.insert("ys".to_owned(),
+                   Value::from(vec![Value::from(y), Value::from(1)]));
+        Value::from(CborTag::new(7, map))
+    }
+
+    assert!(doc(1.0, 2.5).approx_eq(&doc(1.0 + 1e-9, 2.5), 1e-6));
+    assert!(doc(1.0, 0.1).approx_eq(&doc(1.0, 0.1 + 1e-7), 1e-6));
+    assert!(!doc(1.0, 2.5).approx_eq(&doc(1.1, 2.5), 1e-6));
+    assert!(!doc(1.0, 2.5).approx_eq(&doc(1.0, 2.6), 0.01));
+
+    let nan = Value::from(::std::f64::NAN);
+    assert!(nan.approx_eq(&nan, 0.0));
+    let inf = Value::from(::std::f64::INFINITY);
+    assert!(inf.approx_eq(&inf, 0.0));
+    assert!(!inf.approx_eq(&Value::from(-::std::f64::INFINITY), 1.0));
+    assert!(!Value::from(1.0).approx_eq(&Value::from(1), 1.0));
+    assert!(!Value::from(vec![Value::from(1.0)])
+        .approx_eq(&Value::from(vec![]), 1.0));
+}
+
+#[test]
+fn compact_enums() {
+    #[derive(Debug, PartialEq, RustcDecodable, RustcEncodable)]
+    enum Color {
+        Red,
+        Blue(u8),
+        Green(Vec<u8>, String),
+        Custom(Vec<u8>),
+    }
+
+    let colors = vec![
+        Color::Red,
+        Color::Blue(5),
+        Color::Green(vec![1], "lime".to_owned()),
+        Color::Custom(vec![1, 2, 3]),
+    ];
+    let mut enc = Encoder::from_memory();
+    enc.set_compact_enums(true);
+    enc.encode(&[&colors]).unwrap();
+    let bytes = enc.into_bytes();
+    assert_eq!(&bytes[..7], &[
+        0x84,
+        0x63, b'R', b'e', b'd',
+        0xa1, 0x64,
+    ]);
+    assert_eq!(&bytes[11..12], &[0x05]);
+    let v = readone(&bytes);
+    assert_eq!(v[2]["Green"][1].as_str(), Some("lime"));
+    assert_eq!(v[3]["Custom"][0][2].as_u64(), Some(3));
+    assert_eq!(decode::<Vec<Color>>(&bytes), colors);
+
+    // Unit variants can also be indices.
+    let mut enc = Encoder::from_memory();
+    enc.set_compact_enums(true);
+    enc.set_unit_variants_as_index(true);
+    enc.encode(&[&colors]).unwrap();
+    assert_eq!(enc.as_bytes()[1], 0x00);
+    assert_eq!(decode::<Vec<Color>>(enc.as_bytes()), colors);
+}
+
+#[test]
+fn clone_remaining() {
+    let mut bytes = encode(("header", 1));
+    bytes.extend(encode(vec!["a", "b"]));
+    bytes.extend(encode(true));
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    let header: (String, u8) = dec.decode().next().unwrap().unwrap();
+    assert_eq!(header, ("header".to_owned(), 1));
+    let rest = dec.clone_remaining();
+    assert_eq!(rest, dec.remaining());
+
+    let mut later = Decoder::from_bytes(rest);
+    let items: Vec<Cbor> = later.items().collect::<Result<_, _>>().unwrap();
+    assert_eq!(items, vec![readone(&encode(vec!["a", "b"])),
+                           Cbor::Bool(true)]);
+
+    // The original decoder is unaffected.
+    let strs: Vec<String> = dec.decode().next().unwrap().unwrap();
+    assert_eq!(strs, vec!["a", "b"]);
+    dec.decode::<bool>().next().unwrap().unwrap();
+    assert!(dec.clone_remaining().is_empty());
+}
+
+#[test]
+fn max_items() {
+    // [1, [2, 3], 1(4)] has 7 data items, including the tag.
+    let bytes = &[0x83, 0x01, 0x82, 0x02, 0x03, 0xc1, 0x04];
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_max_items(7);
+    assert!(dec.items().next().unwrap().is_ok());
+
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_max_items(6);
+    assert!(dec.items().next().unwrap().is_err());
+
+    // Lots of tiny items in an indefinite length array. The break counts.
+    let mut many = vec![0x9f];
+    many.extend(vec![0xf6; 1000]);
+    many.push(0xff);
+    let mut dec = Decoder::from_bytes(&many[..]);
+    dec.set_max_items(1001);
+    assert!(dec.items().next().unwrap().is_err());
+    let mut dec = Decoder::from_bytes(&many[..]);
+    dec.set_max_items(1002);
+    assert!(dec.items().next().unwrap().is_ok());
+
+    // The count starts over for each top-level data item.
+    let mut dec = Decoder::from_bytes(encode((1, 2)).repeat(3));
+    dec.set_max_items(3);
+    assert_eq!(dec.items().collect::<Result<Vec<_>, _>>().unwrap().len(), 3);
+
+    // Reading element by element doesn't start the count over.
+    let mut dec = Decoder::from_bytes(&many[..]);
+    dec.set_max_items(1001);
+    let (vals, err) = dec.decode_vec_partial::<Option<u8>>();
+    assert_eq!(vals.len(), 1000);
+    assert!(err.is_some());
+    let mut dec = Decoder::from_bytes(&many[..]);
+    dec.set_max_items(500);
+    let (vals, err) = dec.decode_vec_partial::<Option<u8>>();
+    assert_eq!(vals.len(), 499);
+    assert!(err.is_some());
+
+    // Headers read as events count too.
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    dec.set_max_items(6);
+    let events: Vec<_> = dec.events().collect();
+    assert_eq!(events.len(), 7);
+    assert!(events[..6].iter().all(|e| e.is_ok()));
+    assert!(events[6].is_err());
+}
+
+#[test]
+fn pointer_mut() {
+    use cbor::Value;
+
+    let mut user = HashMap::new();
+    user.insert("name".to_owned(), Value::from("ann"));
+    user.insert("tags".to_owned(), Value::from(vec![Value::from("a")]));
+    let mut doc = HashMap::new();
+    doc.insert("users".to_owned(), Value::from(vec![Value::from(user)]));
+    let mut v = readone(&encode(Value::from(doc)));
+
+    *v.pointer_mut("/users/0/name").unwrap() = Value::from("bob");
+    {
+        let user = v.get_mut("users").unwrap().get_index_mut(0).unwrap();
+        assert!(user.get_mut("age").is_none());
+        *user.get_mut("tags").unwrap().get_index_mut(0).unwrap() =
+            Value::from(true);
+    }
+    assert!(v.pointer_mut("/users/1").is_none());
+    assert!(v.pointer_mut("/users/00").is_none());
+    assert!(v.pointer_mut("users").is_none());
+
+    let v = readone(&encode(&v));
+    assert_eq!(v.pointer("/users/0/name"), Some(&Value::from("bob")));
+    assert_eq!(v.pointer("/users/0/tags/0"), Some(&Value::from(true)));
+    assert_eq!(v.pointer(""), Some(&v));
+}
+
+#[test]
+fn json_bytes_round_trip() {
+    use rustc_serialize::json::Json;
+
+    let doc = r#"{
+        "name": "cbor",
+        "version": [0, 3, 7],
+        "meta": {"stable": false, "score": -1.25, "owner": null},
+        "ids": [-1, 18446744073709551615]
+    }"#;
+    let bytes = cbor::json_to_cbor_bytes(doc).unwrap();
+    let v = readone(&bytes);
+    assert_eq!(v["version"][2].as_u64(), Some(7));
+    assert_eq!(v["meta"]["score"].as_f64(), Some(-1.25));
+    let json = cbor::cbor_bytes_to_json(&bytes).unwrap();
+    assert_eq!(Json::from_str(&json).unwrap(), Json::from_str(doc).unwrap());
+
+    // Lossy: byte strings become base64, and tags are dropped.
+    let lossy = encode((CborBytes(vec![0xff]), CborTime(
+        ::std::time::UNIX_EPOCH + ::std::time::Duration::from_secs(5))));
+    assert_eq!(cbor::cbor_bytes_to_json(&lossy).unwrap(), r#"["/w==",5]"#);
+
+    assert!(cbor::json_to_cbor_bytes("{").is_err());
+    assert!(cbor::cbor_bytes_to_json(&[0x01, 0x02]).is_err());
+    assert!(cbor::cbor_bytes_to_json(&[0xff]).is_err());
+    assert!(cbor::cbor_bytes_to_json(&[]).is_err());
+}
+
+#[test]
+fn decode_tuple_struct() {
+    #[derive(Debug, PartialEq)]
+    struct Record(u32, String, Vec<bool>);
+
+    fn read(bytes: &[u8]) -> Result<Record, cbor::CborError> {
+        Decoder::from_bytes(bytes).decode_tuple_struct(3, |fields| {
+            Ok(Record(try!(fields.field()),
+                      try!(fields.field()),
+                      try!(fields.field())))
+        })
+    }
+
+    let want = Record(7, "seven".to_owned(), vec![true, false]);
+    let bytes = encode((7, "seven", vec![true, false]));
+    assert_eq!(read(&bytes).unwrap(), want);
+    // [_ 7, "seven", [true, false]]
+    let mut indefinite = vec![0x9f];
+    indefinite.extend(&bytes[1..]);
+    indefinite.push(0xff);
+    assert_eq!(read(&indefinite).unwrap(), want);
+
+    assert!(read(&encode((7, "seven"))).is_err());
+    assert!(read(&encode((7, "seven", vec![true], 8))).is_err());
+    assert!(read(&[0x9f, 0x07, 0xff]).is_err());
+    let mut longer = indefinite.clone();
+    longer.insert(longer.len() - 1, 0x08);
+    assert!(read(&longer).is_err());
+    assert!(read(&encode((7, 8, vec![true]))).is_err());
+
+    // Reading too few or too many fields is an error too.
+    let mut dec = Decoder::from_bytes(&bytes[..]);
+    assert!(dec.decode_tuple_struct(3, |fields| fields.field::<u32>())
+               .is_err());
+    let mut dec = Decoder::from_bytes(&encode((1, 2))[..]);
+    assert!(dec.decode_tuple_struct(2, |fields| {
+        for _ in 0..3 {
+            try!(fields.field::<u32>());
+        }
+        Ok(())
+    }).is_err());
+}
+
+#[test]
+fn encode_cbor_keeps_widths_in_collections() {
+    use cbor::{CborSigned, CborUnsigned};
+
+    fn encode_cbor(v: &Cbor) -> Vec<u8> {
+        let mut enc = Encoder::from_memory();
+        enc.encode_cbor(v).unwrap();
+        enc.into_bytes()
+    }
+
+    let array = Cbor::Array(vec![
+        Cbor::Unsigned(CborUnsigned::UInt32(5)),
+        Cbor::Unsigned(CborUnsigned::UInt8(5)),
+        Cbor::Signed(CborSigned::Int32(-200)),
+    ]);
+    assert_eq!(encode_cbor(&array), vec![
+        0x83, 0x1a, 0x00, 0x00, 0x00, 0x05, 0x05, 0x3a, 0x00, 0x00, 0x00, 0xc7,
+    ]);
+    assert_eq!(readone(&encode_cbor(&array)), array);
+
+    let mut map = HashMap::new();
+    map.insert("a".to_owned(), Cbor::Unsigned(CborUnsigned::UInt64(5)));
+    assert_eq!(encode_cbor(&Cbor::Map(map)), vec![
+        0xa1, 0x61, 0x61, 0x1b, 0, 0, 0, 0, 0, 0, 0, 0x05,
+    ]);
+
+    // Canonicalizing normalizes the widths.
+    let mut canonical = array.clone();
+    canonical.canonicalize();
+    assert_eq!(encode_cbor(&canonical), vec![0x83, 0x05, 0x05, 0x38, 0xc7]);
+}
+
+// The examples from appendix A of RFC 8949 (and RFC 7049), as the hex
+// encoding, the expected value in diagnostic notation and whether the
+// encoding is canonical. An expected value of `None` marks a vector that is
+// expected to fail to decode.
+const APPENDIX_A: &'static [(&'static str, Option<&'static str>, bool)] = &[
+    ("00", Some("0"), true),
+    ("01", Some("1"), true),
+    ("0a", Some("10"), true),
+    ("17", Some("23"), true),
+    ("1818", Some("24"), true),
+    ("1819", Some("25"), true),
+    ("1864", Some("100"), true),
+    ("1903e8", Some("1000"), true),
+    ("1a000f4240", Some("1000000"), true),
+    ("1b000000e8d4a51000", Some("1000000000000"), true),
+    ("1bffffffffffffffff", Some("18446744073709551615"), true),
+    ("c249010000000000000000", Some("2(h'010000000000000000')"), true),
+    ("c349010000000000000000", Some("3(h'010000000000000000')"), true),
+    ("20", Some("-1"), true),
+    ("29", Some("-10"), true),
+    ("3863", Some("-100"), true),
+    ("3903e7", Some("-1000"), true),
+    // -18446744073709551616 doesn't fit in an `i64`.
+    ("3bffffffffffffffff", None, true),
+    ("f90000", Some("0.0"), true),
+    ("f98000", Some("-0.0"), true),
+    ("f93c00", Some("1.0"), true),
+    ("fb3ff199999999999a", Some("1.1"), true),
+    ("f93e00", Some("1.5"), true),
+    ("f97bff", Some("65504.0"), true),
+    ("fa47c35000", Some("100000.0"), true),
+    ("fa7f7fffff", Some("3.4028234663852886e38"), true),
+    ("fb7e37e43c8800759c", Some("1e300"), true),
+    ("f90001", Some("5.960464477539063e-8"), true),
+    ("f90400", Some("6.103515625e-5"), true),
+    ("f9c400", Some("-4.0"), true),
+    ("fbc010666666666666", Some("-4.1"), true),
+    ("f97c00", Some("Infinity"), true),
+    ("f97e00", Some("NaN"), true),
+    ("f9fc00", Some("-Infinity"), true),
+    ("fa7f800000", Some("Infinity"), false),
+    ("fa7fc00000", Some("NaN"), false),
+    ("faff800000", Some("-Infinity"), false),
+    ("fb7ff0000000000000", Some("Infinity"), false),
+    ("fb7ff8000000000000", Some("NaN"), false),
+    ("fbfff0000000000000", Some("-Infinity"), false),
+    ("f4", Some("false"), true),
+    ("f5", Some("true"), true),
+    ("f6", Some("null"), true),
+    ("f7", Some("undefined"), true),
+    ("f0", Some("simple(16)"), true),
+    // Only in RFC 7049, since RFC 8949 made it not well-formed.
+    ("f818", None, false),
+    ("f8ff", Some("simple(255)"), true),
+    ("c074323031332d30332d32315432303a30343a30305a",
+     Some("0(\"2013-03-21T20:04:00Z\")"), true),
+    ("c11a514b67b0", Some("1(1363896240)"), true),
+    ("c1fb41d452d9ec200000", Some("1(1363896240.5)"), true),
+    ("d74401020304", Some("23(h'01020304')"), true),
+    ("d818456449455446", Some("24(h'6449455446')"), true),
+    ("d82076687474703a2f2f7777772e6578616d706c652e636f6d",
+     Some("32(\"http://www.example.com\")"), true),
+    ("40", Some("h''"), true),
+    ("4401020304", Some("h'01020304'"), true),
+    ("60", Some("\"\""), true),
+    ("6161", Some("\"a\""), true),
+    ("6449455446", Some("\"IETF\""), true),
+    ("62225c", Some("\"\\\"\\\\\""), true),
+    ("62c3bc", Some("\"\u{fc}\""), true),
+    ("63e6b0b4", Some("\"\u{6c34}\""), true),
+    ("64f0908591", Some("\"\u{10151}\""), true),
+    ("80", Some("[]"), true),
+    ("83010203", Some("[1, 2, 3]"), true),
+    ("8301820203820405", Some("[1, [2, 3], [4, 5]]"), true),
+    ("98190102030405060708090a0b0c0d0e0f101112131415161718181819",
+     Some("[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, \
+      19, 20, 21, 22, 23, 24, 25]"), true),
+    ("a0", Some("{}"), true),
+    ("a26161016162820203", Some("{\"a\": 1, \"b\": [2, 3]}"), true),
+    ("826161a161626163", Some("[\"a\", {\"b\": \"c\"}]"), true),
+    ("a56161614161626142616361436164614461656145",
+     Some("{\"a\": \"A\", \"b\": \"B\", \"c\": \"C\", \"d\": \"D\", \
+      \"e\": \"E\"}"), true),
+    ("5f42010243030405ff", Some("h'0102030405'"), false),
+    ("7f657374726561646d696e67ff", Some("\"streaming\""), false),
+    ("9fff", Some("[]"), false),
+    ("9f018202039f0405ffff", Some("[1, [2, 3], [4, 5]]"), false),
+    ("9f01820203820405ff", Some("[1, [2, 3], [4, 5]]"), false),
+    ("83018202039f0405ff", Some("[1, [2, 3], [4, 5]]"), false),
+    ("83019f0203ff820405", Some("[1, [2, 3], [4, 5]]"), false),
+    ("9f0102030405060708090a0b0c0d0e0f101112131415161718181819ff",
+     Some("[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, \
+      19, 20, 21, 22, 23, 24, 25]"), false),
+    ("bf61610161629f0203ffff", Some("{\"a\": 1, \"b\": [2, 3]}"), false),
+    ("826161bf61626163ff", Some("[\"a\", {\"b\": \"c\"}]"), false),
+    ("bf6346756ef563416d7421ff", Some("{\"Amt\": -2, \"Fun\": true}"), false),
+];
+
+#[test]
+fn appendix_a_vectors() {
+    let mut failures = vec![];
+    for (i, &(hex, diag, canonical)) in APPENDIX_A.iter().enumerate() {
+        let bytes = hex.from_hex().unwrap();
+        let item = Decoder::from_bytes(&bytes[..]).items().next();
+        let (mut v, diag) = match (item, diag) {
+            (Some(Ok(v)), Some(diag)) => (v, diag),
+            (Some(Err(_)), None) => continue,
+            (r, _) => {
+                failures.push(format!("{}: {} decoded as {:?}", i, hex, r));
+                continue;
+            }
+        };
+        if v.to_string() != diag {
+            failures.push(format!("{}: {} decoded as {}, expected {}",
+                                  i, hex, v, diag));
+        }
+        if canonical {
+            v.canonicalize();
+            let mut enc = Encoder::from_memory();
+            enc.encode_cbor(&v).unwrap();
+            if enc.as_bytes() != &bytes[..] {
+                failures.push(format!("{}: {} re-encoded as {}",
+                                      i, hex, enc.as_bytes().to_hex()));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "failed vectors:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn diff_maps() {
+    use cbor::{Change, PathSegment, diff};
+
+    fn cbor<T: Encodable>(v: T) -> Cbor { readone(&encode(v)) }
+
+    let mut a = HashMap::new();
+    a.insert("name".to_owned(), cbor("x".to_owned()));
+    a.insert("tags".to_owned(), cbor(vec![1, 2]));
+    let mut b = a.clone();
+    b.insert("tags".to_owned(), cbor(vec![1, 3]));
+    b.insert("new".to_owned(), cbor(true));
+
+    let changes = diff(&Cbor::Map(a.clone()), &Cbor::Map(b));
+    assert_eq!(changes, vec![
+        Change::Added {
+            path: vec![PathSegment::Key("new".to_owned())],
+            value: Cbor::Bool(true),
+        },
+        Change::Changed {
+            path: vec![PathSegment::Key("tags".to_owned()),
+                       PathSegment::Index(1)],
+            old: cbor(2),
+            new: cbor(3),
+        },
+    ]);
+    assert!(diff(&Cbor::Map(a.clone()), &Cbor::Map(a)).is_empty());
+}
+
+#[test]
+fn roundtrip_bit_vec() {
+    use cbor::CborBitVec;
+
+    let bits = CborBitVec((0..1000).map(|i| i % 3 == 0 || i % 7 == 0)
+                                   .collect());
+    let bytes = encode(&bits);
+    // 125 bytes of bits plus the tag, array, length and string headers.
+    assert!(bytes.len() >= 125 && bytes.len() <= 135, "{}", bytes.len());
+    round_trip(bits);
+    round_trip(CborBitVec(vec![]));
+
+    // The byte string must have exactly enough bytes for the length.
+    let mut enc = Encoder::from_memory();
+    enc.encode(&[CborTagEncode::new(CborBitVec::TAG,
+                                    &(9, CborBytes(vec![0xff])))]).unwrap();
+    let mut dec = Decoder::from_bytes(enc.as_bytes());
+    assert!(dec.decode::<CborBitVec>().next().unwrap().is_err());
+}